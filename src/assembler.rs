@@ -2,7 +2,7 @@ use std::{error, fs};
 
 use crate::{
     backend::{
-        gen_code::{self, SectionName},
+        gen_code::{self, Options, SectionName},
         gen_elf,
     },
     common::error::Error,
@@ -15,12 +15,13 @@ use crate::{
 pub fn assemble_to_file(
     input_file: String,
     output_file: String,
+    options: Options,
 ) -> Result<(), Box<dyn error::Error>> {
     let source = SourceFile {
         filename: input_file.clone(),
         content: fs::read_to_string(input_file)?,
     };
-    let output = assemble(source)?;
+    let output = assemble_with(source, options)?;
     fs::write(output_file, output)?;
     Ok(())
 }
@@ -28,28 +29,37 @@ pub fn assemble_to_file(
 pub fn assemble_raw_to_file(
     input_file: String,
     output_file: String,
+    options: Options,
 ) -> Result<(), Box<dyn error::Error>> {
     let source = SourceFile {
         filename: input_file.clone(),
         content: fs::read_to_string(input_file)?,
     };
-    let output = assemble_raw(source)?;
+    let output = assemble_raw_with(source, options)?;
     fs::write(output_file, output)?;
     Ok(())
 }
 
 pub fn assemble(source: SourceFile) -> Result<Vec<u8>, Error> {
+    assemble_with(source, Options::default())
+}
+
+pub fn assemble_with(source: SourceFile, options: Options) -> Result<Vec<u8>, Error> {
     lexer::tokenize(source)
         .and_then(parser::parse)
-        .and_then(gen_code::generate)
+        .and_then(|program| gen_code::generate_with(program, options))
         .and_then(gen_elf::generate)
         .map(|elf| elf.to_bytes())
 }
 
 pub fn assemble_raw(source: SourceFile) -> Result<Vec<u8>, Error> {
+    assemble_raw_with(source, Options::default())
+}
+
+pub fn assemble_raw_with(source: SourceFile, options: Options) -> Result<Vec<u8>, Error> {
     let obj = lexer::tokenize(source)
         .and_then(parser::parse)
-        .and_then(gen_code::generate)?;
+        .and_then(|program| gen_code::generate_with(program, options))?;
 
     let text_section = obj
         .sections
@@ -1,39 +1,80 @@
+pub mod check_operand;
 pub mod collect_symbol;
+pub mod encode_bit;
+pub mod encode_evex;
+pub mod encode_flow;
+pub mod encode_prefix;
+pub mod encode_segment;
+pub mod encode_sse;
+pub mod encode_string;
+pub mod encode_sync;
+pub mod encode_system;
+pub mod encode_vex;
 pub mod generate_code;
+pub mod mode;
+pub mod modrm;
+pub mod relax_branch;
 pub mod resolve_symbol;
+pub mod section_stack;
+pub mod select_imm;
 
 use std::collections::HashMap;
 
-use x86asm::{encode, instruction::Instruction};
+use x86asm::{
+    encode,
+    instruction::{
+        operand::{offset::Offset, Operand},
+        Instruction,
+    },
+};
 
 use crate::{
     backend::gen_code::{
         collect_symbol::SymbolCollector,
         generate_code::CodeGen,
-        resolve_symbol::{list_global_symbols, resolve_symbol},
+        relax_branch::relax_branches,
+        resolve_symbol::{list_global_symbols, relocate_symbols, resolve_symbol, resolve_tses},
     },
-    common::error::Error,
+    common::{error::Error, pos::Pos},
     frontend::parser::node::Program,
 };
 
+#[derive(Default, Clone, Debug)]
+pub struct Options {
+    // always use rel32 for branches that also have a rel8 form
+    pub force_long_branches: bool,
+}
+
 pub fn generate(program: Program) -> Result<Object, Error> {
+    generate_with(program, Options::default())
+}
+
+pub fn generate_with(program: Program, options: Options) -> Result<Object, Error> {
     let collector = SymbolCollector::new();
-    let (symbols, tses) = collector.collect_symbols(&program);
+    let (symbols, tses) = collector.collect_symbols(&program)?;
 
-    let generator = CodeGen::new();
-    let mut codes = generator.gen_program(program);
+    let generator = CodeGen::new(options);
+    let mut codes = generator.gen_program(program)?;
+
+    let mut sections = gen_sections(&symbols, &mut codes)?;
+    sections.extend(resolve_tses(&symbols, &codes, tses)?);
 
     Ok(Object {
-        sections: gen_sections(&symbols, &mut codes),
+        sections,
         global_symbols: list_global_symbols(symbols, &codes),
-        tses,
     })
 }
 
-fn gen_sections(symbols: &Symbols, codes: &mut Codes) -> Vec<Section> {
+fn gen_sections(symbols: &Symbols, codes: &mut Codes) -> Result<Vec<Section>, Error> {
+    // absolute relocations need the final byte address of every symbol
+    for (section_name, code) in codes.iter_mut() {
+        relax_branches(symbols, section_name, code);
+    }
+    let relocated_symbols = relocate_symbols(symbols, codes);
+
     let mut sections = Vec::new();
     for (section_name, code) in codes.iter_mut() {
-        let section_relas = resolve_symbol(&symbols, code);
+        let section_relas = resolve_symbol(&symbols, &relocated_symbols, code)?;
 
         let section_data: Vec<u8> = code
             .items
@@ -47,13 +88,20 @@ fn gen_sections(symbols: &Symbols, codes: &mut Codes) -> Vec<Section> {
             relas: section_relas,
         })
     }
-    sections
+    Ok(sections)
 }
 
 fn encode_item(item: &CodeItem) -> Vec<u8> {
     match item {
         CodeItem::Raw(data) => data.clone(),
         CodeItem::Inst(inst) => encode::encode(inst),
+        CodeItem::Branch16(inst) => {
+            let mut bytes = encode::encode(inst);
+            if let Some(Operand::Offset(Offset::Off32(_))) = inst.operand1 {
+                bytes.truncate(bytes.len() - 2);
+            }
+            bytes
+        }
     }
 }
 
@@ -64,7 +112,6 @@ type Codes = HashMap<SectionName, Code>;
 pub struct Object {
     pub sections: Vec<Section>,
     pub global_symbols: Vec<Symbol>,
-    pub tses: Vec<Tse>,
 }
 
 #[derive(Debug)]
@@ -87,18 +134,26 @@ pub struct Rela {
     pub name: String,
     pub typ: RelaType,
     pub offset: u32,
+    pub addend: i64,
 }
 
 #[derive(Debug)]
 pub enum RelaType {
+    Pc16,
     Pc32,
     Plt32,
+    Abs16,
+    Abs32,
+    Abs32S,
+    Abs64,
 }
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub enum SectionName {
     Data,
     Text,
+    TseInfo,
+    Other(String),
 }
 
 impl SectionName {
@@ -106,6 +161,18 @@ impl SectionName {
         match self {
             SectionName::Data => ".data",
             SectionName::Text => ".text",
+            SectionName::TseInfo => ".tse_info",
+            SectionName::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for SectionName {
+    fn from(name: &str) -> Self {
+        match name {
+            ".data" => SectionName::Data,
+            ".text" => SectionName::Text,
+            x => SectionName::Other(x.to_string()),
         }
     }
 }
@@ -120,6 +187,8 @@ pub struct Code {
 enum CodeItem {
     Raw(Vec<u8>),
     Inst(Instruction),
+    // a branch in 16-bit code, whose rel32 is cut down to a rel16
+    Branch16(Instruction),
 }
 
 #[derive(Debug)]
@@ -127,12 +196,24 @@ struct UnresolvedSymbol {
     symbol_name: String,
     typ: SymbolType,
     item_index: usize,
+    addend: i64,
+    // the number of bytes of the item after the relocated field
+    trailing: u32,
+    pos: Pos,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum SymbolType {
     Addr,
     Jump,
+    // the imm16 of a 16-bit operation
+    Abs16,
+    // a zero-extended imm32
+    Abs32,
+    // a sign-extended imm32
+    Abs32S,
+    // the 8-byte immediate of `mov r64, imm64`
+    Abs64,
 }
 
 #[derive(Debug)]
@@ -141,4 +222,5 @@ pub struct Tse {
     pub offset: i64,
     pub size: u64,
     pub align: u64,
+    pub pos: Pos,
 }
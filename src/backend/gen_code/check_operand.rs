@@ -0,0 +1,266 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{encode_evex::has_evex_form, encode_sse::is_sse, encode_vex::is_vex},
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// rejects operand combinations that the encoder would otherwise turn into wrong bytes
+pub fn check_operands(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<(), Error> {
+    // masking, broadcast and rounding only exist in EVEX encodings
+    if !has_evex_form(op) && oprs.iter().any(is_decorated) {
+        return Err(invalid_operands(op, pos));
+    }
+
+    // far pointers are only jumped to or called, and only by jmp and call
+    let is_far_branch = matches!(op, Mnemonic::Call | Mnemonic::Jmp) && oprs.len() == 1;
+    for opr in oprs {
+        match opr {
+            OperandNode::FarPointer { .. } if !is_far_branch => {
+                return Err(invalid_operands(op, pos));
+            }
+            opr if operand_size(opr) == Some(OperandSize::Fword) && !is_far_branch => {
+                return Err(unexpected_size(opr, pos));
+            }
+            _ => {}
+        }
+    }
+
+    if is_cmov(op) {
+        for opr in oprs {
+            if operand_size(opr) == Some(OperandSize::Byte) {
+                return Err(unexpected_size(opr, pos));
+            }
+        }
+    }
+
+    // only vector instructions take 128-bit, 256-bit and 512-bit memory
+    if !is_sse(op) && !is_vex(op) && !has_evex_form(op) {
+        use OperandSize::*;
+        for opr in oprs {
+            if matches!(
+                operand_size(opr),
+                Some(Xmmword) | Some(Ymmword) | Some(Zmmword)
+            ) {
+                return Err(unexpected_size(opr, pos));
+            }
+        }
+    }
+
+    // the source size selects the encoding of extending moves
+    if let [_, src] = oprs {
+        use OperandSize::*;
+        let src_size = operand_size(src);
+        let is_valid = match op {
+            Mnemonic::Movzx => matches!(src_size, Some(Byte) | Some(Word)),
+            Mnemonic::Movsx => matches!(src_size, Some(Byte) | Some(Word) | Some(Dword)),
+            Mnemonic::Movsxd => matches!(src_size, Some(Dword)),
+            _ => true,
+        };
+        if !is_valid {
+            return Err(unexpected_size(src, pos));
+        }
+    }
+
+    // ah-dh are encoded as spl-dil whenever the instruction has a REX prefix
+    if oprs.iter().any(requires_rex) {
+        for opr in oprs {
+            if let OperandNode::Register(reg) = opr {
+                if is_high_byte(reg) {
+                    return Err(unexpected_register(reg, pos));
+                }
+            }
+        }
+    }
+
+    // indirect branches always take a 64-bit target, unless they are far
+    if matches!(op, Mnemonic::Call | Mnemonic::Jmp) {
+        if let [opr] = oprs {
+            let size = operand_size(opr);
+            if !matches!(
+                size,
+                None | Some(OperandSize::Qword) | Some(OperandSize::Fword)
+            ) {
+                return Err(unexpected_size(opr, pos));
+            }
+        }
+    }
+
+    // the shift count is either an immediate or cl
+    if is_shift(op) {
+        if let Some(OperandNode::Register(reg)) = oprs.last() {
+            if oprs.len() > 1 && *reg != Register::Cl {
+                return Err(unexpected_register(reg, pos));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_shift(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(op, Rcl | Rcr | Rol | Ror | Sar | Shl | Shld | Shr | Shrd)
+}
+
+fn is_cmov(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        op,
+        Cmova
+            | Cmovae
+            | Cmovb
+            | Cmovbe
+            | Cmove
+            | Cmovg
+            | Cmovge
+            | Cmovl
+            | Cmovle
+            | Cmovne
+            | Cmovno
+            | Cmovnp
+            | Cmovns
+            | Cmovo
+            | Cmovp
+            | Cmovs
+    )
+}
+
+fn is_decorated(opr: &OperandNode) -> bool {
+    match opr {
+        OperandNode::Masked { .. } | OperandNode::Rounding(_) => true,
+        OperandNode::Memory(mem) => mem.broadcast.is_some(),
+        _ => false,
+    }
+}
+
+fn requires_rex(opr: &OperandNode) -> bool {
+    use Register::*;
+    match opr {
+        OperandNode::Register(reg) => {
+            is_extended(reg)
+                || matches!(reg, Spl | Bpl | Sil | Dil)
+                || register_size(reg) == Some(OperandSize::Qword)
+        }
+        OperandNode::Memory(mem) => {
+            mem.base.as_ref().map_or(false, is_extended)
+                || mem
+                    .index
+                    .as_ref()
+                    .map_or(false, |index| is_extended(&index.reg))
+                || mem.size == Some(OperandSize::Qword)
+        }
+        _ => false,
+    }
+}
+
+// r8-r15 in any size
+pub fn is_extended(reg: &Register) -> bool {
+    use Register::*;
+    matches!(
+        reg,
+        R8 | R9
+            | R10
+            | R11
+            | R12
+            | R13
+            | R14
+            | R15
+            | R8d
+            | R9d
+            | R10d
+            | R11d
+            | R12d
+            | R13d
+            | R14d
+            | R15d
+            | R8w
+            | R9w
+            | R10w
+            | R11w
+            | R12w
+            | R13w
+            | R14w
+            | R15w
+            | R8b
+            | R9b
+            | R10b
+            | R11b
+            | R12b
+            | R13b
+            | R14b
+            | R15b
+    )
+}
+
+fn is_high_byte(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, Ah | Ch | Dh | Bh)
+}
+
+pub fn operand_size(opr: &OperandNode) -> Option<OperandSize> {
+    match opr {
+        OperandNode::Register(reg) => register_size(reg),
+        OperandNode::Memory(mem) => mem.size,
+        _ => None,
+    }
+}
+
+pub fn register_size(reg: &Register) -> Option<OperandSize> {
+    use Register::*;
+    match reg {
+        Rax | Rcx | Rdx | Rbx | Rsp | Rbp | Rsi | Rdi => Some(OperandSize::Qword),
+        R8 | R9 | R10 | R11 | R12 | R13 | R14 | R15 => Some(OperandSize::Qword),
+        Eax | Ecx | Edx | Ebx | Esp | Ebp | Esi | Edi => Some(OperandSize::Dword),
+        R8d | R9d | R10d | R11d | R12d | R13d | R14d | R15d => Some(OperandSize::Dword),
+        Ax | Cx | Dx | Bx | Sp | Bp | Si | Di => Some(OperandSize::Word),
+        R8w | R9w | R10w | R11w | R12w | R13w | R14w | R15w => Some(OperandSize::Word),
+        Al | Cl | Dl | Bl | Spl | Bpl | Sil | Dil => Some(OperandSize::Byte),
+        R8b | R9b | R10b | R11b | R12b | R13b | R14b | R15b => Some(OperandSize::Byte),
+        Ah | Ch | Dh | Bh => Some(OperandSize::Byte),
+        _ => None,
+    }
+}
+
+// reports the register itself, or the size of a memory operand
+fn unexpected_size(opr: &OperandNode, pos: &Pos) -> Error {
+    match opr {
+        OperandNode::Register(reg) => unexpected_register(reg, pos),
+        OperandNode::Memory(mem) => match mem.size {
+            Some(size) => unexpected_operand_size(size, pos),
+            None => Error::new(pos.clone(), ErrorKind::MissingOperandSize),
+        },
+        _ => Error::new(pos.clone(), ErrorKind::MissingOperandSize),
+    }
+}
+
+// the errors shared by the encoders
+pub fn invalid_operands(op: &Mnemonic, pos: &Pos) -> Error {
+    Error::new(
+        pos.clone(),
+        ErrorKind::InvalidOperands {
+            mnemonic: op.clone(),
+        },
+    )
+}
+
+pub fn unexpected_register(reg: &Register, pos: &Pos) -> Error {
+    Error::new(
+        pos.clone(),
+        ErrorKind::UnexpectedRegister {
+            actual: reg.clone(),
+        },
+    )
+}
+
+pub fn unexpected_operand_size(actual: OperandSize, pos: &Pos) -> Error {
+    Error::new(pos.clone(), ErrorKind::UnexpectedOperandSize { actual })
+}
+
+pub fn out_of_range(value: i64, pos: &Pos) -> Error {
+    Error::new(pos.clone(), ErrorKind::IntegerOutOfRange { value })
+}
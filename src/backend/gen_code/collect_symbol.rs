@@ -1,17 +1,26 @@
 use std::collections::HashMap;
 
 use crate::{
-    backend::gen_code::{SectionName, Symbol, Symbols, Tse},
+    backend::gen_code::{
+        section_stack::{SectionStack, Subsection},
+        SectionName, Symbol, Symbols, Tse,
+    },
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
     frontend::parser::node::{DispNode, InstructionNode, OperandNode, Program, PseudoOp},
 };
 
 pub struct SymbolCollector {
     symbols: Symbols,
     tses: Vec<Tse>,
-    current_section: SectionName,
-    current_symbol: String,
+    sections: SectionStack,
+    current_label: Option<String>,
 
-    current_addr: HashMap<SectionName, usize>,
+    current_addr: HashMap<Subsection, usize>,
+    // holds the subsection each label is defined in
+    label_subsections: HashMap<String, u32>,
 }
 
 impl Symbol {
@@ -36,22 +45,20 @@ impl SymbolCollector {
         Self {
             symbols: HashMap::new(),
             tses: Vec::new(),
-            current_section: SectionName::Text,
-            current_symbol: String::new(),
+            sections: SectionStack::new(),
+            current_label: None,
             current_addr: HashMap::new(),
+            label_subsections: HashMap::new(),
         }
     }
 
-    pub fn collect_symbols(mut self, program: &Program) -> (Symbols, Vec<Tse>) {
-        for inst in &program.insts {
-            self.collect_symbols_in(inst);
+    pub fn collect_symbols(mut self, program: &Program) -> Result<(Symbols, Vec<Tse>), Error> {
+        for stmt in &program.stmts {
+            let inst = &stmt.node;
+            self.collect_symbols_in(inst, &stmt.pos)?;
 
-            if let InstructionNode::PseudoOp(op, _) = inst {
-                match op {
-                    PseudoOp::Data => self.current_section = SectionName::Data,
-                    PseudoOp::Text => self.current_section = SectionName::Text,
-                    _ => {}
-                }
+            if let InstructionNode::PseudoOp(op, args) = inst {
+                self.sections.switch(op, args, &stmt.pos)?;
             }
 
             if is_inst(inst) {
@@ -59,50 +66,100 @@ impl SymbolCollector {
             }
         }
 
-        (self.symbols, self.tses)
+        self.relocate_subsections();
+        Ok((self.symbols, self.tses))
+    }
+
+    // makes label addresses relative to the start of the section
+    // instead of the start of the subsection they are defined in
+    fn relocate_subsections(&mut self) {
+        for symbol in self.symbols.values_mut() {
+            let number = match self.label_subsections.get(&symbol.name) {
+                Some(number) => *number,
+                None => continue,
+            };
+
+            let base_addr: usize = self
+                .current_addr
+                .iter()
+                .filter(|(subsection, _)| {
+                    subsection.name == symbol.section && subsection.number < number
+                })
+                .map(|(_, addr)| addr)
+                .sum();
+
+            symbol.addr = symbol.addr.map(|addr| addr + base_addr);
+        }
     }
 
-    fn collect_symbols_in(&mut self, inst: &InstructionNode) {
+    fn collect_symbols_in(&mut self, inst: &InstructionNode, pos: &Pos) -> Result<(), Error> {
         match inst {
             InstructionNode::PseudoOp(PseudoOp::Global, args) => {
                 let name = args[0].as_string();
                 self.add_symbol(name).set_global();
-                self.current_symbol = name.clone();
             }
             InstructionNode::PseudoOp(PseudoOp::Tse, args) => {
+                // the symbol argument is optional
+                let (symbol_name, args) = match args.len() {
+                    4 => (args[0].as_string().clone(), &args[1..]),
+                    _ => {
+                        let label = self
+                            .current_label
+                            .clone()
+                            .ok_or_else(|| Error::new(pos.clone(), ErrorKind::NoPrecedingLabel))?;
+                        (label, &args[..])
+                    }
+                };
+
                 self.tses.push(Tse {
-                    symbol_name: self.current_symbol.clone(),
-                    offset: *args[0].as_integer() as i64,
+                    symbol_name,
+                    offset: *args[0].as_integer(),
                     size: *args[1].as_integer() as u64,
                     align: *args[2].as_integer() as u64,
+                    pos: pos.clone(),
                 });
             }
             InstructionNode::Label(name) => {
+                self.current_label = Some(name.clone());
                 let addr = *self.cur_addr();
-                let cur_section = self.current_section.clone();
+                let cur_section = self.sections.current().clone();
+                self.label_subsections
+                    .insert(name.clone(), cur_section.number);
                 self.add_symbol(name)
                     .set_addr(addr)
-                    .set_section(cur_section);
-            }
-            // TODO
-            InstructionNode::UnaryOp(_, OperandNode::Label(name)) => {
-                self.add_symbol(name);
+                    .set_section(cur_section.name);
             }
-            InstructionNode::BinaryOp(_, _, OperandNode::Memory(mem)) => {
-                if let Some(DispNode::Label(ref name)) = mem.disp {
-                    self.add_symbol(name);
+            InstructionNode::Op(_, _, oprs) => {
+                for opr in oprs {
+                    match opr.unmasked() {
+                        OperandNode::Label { name, .. }
+                        | OperandNode::FarPointer {
+                            offset: DispNode::Label { name, .. },
+                            ..
+                        } => {
+                            self.add_symbol(name);
+                        }
+                        OperandNode::Memory(mem) => {
+                            if let Some(DispNode::Label { ref name, .. }) = mem.disp {
+                                self.add_symbol(name);
+                            }
+                        }
+                        _ => {}
+                    }
                 }
             }
             _ => {}
         }
+        Ok(())
     }
 
     fn add_symbol(&mut self, name: &str) -> &mut Symbol {
+        let section = self.sections.current().name.clone();
         self.symbols.entry(name.to_string()).or_insert(Symbol {
             name: name.to_string(),
             addr: None,
             is_global: false,
-            section: self.current_section.clone(),
+            section,
         })
     }
 
@@ -111,17 +168,16 @@ impl SymbolCollector {
     }
 
     fn cur_addr(&mut self) -> &mut usize {
-        let current_section = self.current_section.clone();
+        let current_section = self.sections.current().clone();
         self.current_addr.entry(current_section).or_insert(0)
     }
 }
 
 fn is_inst(inst: &InstructionNode) -> bool {
-    matches!(inst,
-        InstructionNode::NullaryOp(_)
-        | InstructionNode::UnaryOp(_, _)
-        | InstructionNode::BinaryOp(_, _, _)
-        | InstructionNode::PseudoOp(PseudoOp::Zero, _)
-        | InstructionNode::PseudoOp(PseudoOp::Ascii, _)
+    matches!(
+        inst,
+        InstructionNode::Op(_, _, _)
+            | InstructionNode::PseudoOp(PseudoOp::Zero, _)
+            | InstructionNode::PseudoOp(PseudoOp::Ascii, _)
     )
 }
@@ -0,0 +1,185 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::mnemonic::Mnemonic;
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            invalid_operands, out_of_range, register_size, unexpected_operand_size,
+            unexpected_register,
+        },
+        encode_vex::{vex_prefix, Map, Pp},
+        modrm::{encode_modrm, encode_sized, reg_code, Encoded},
+    },
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// bit tests, scans and counts, and the BMI1 and BMI2 instructions
+pub fn is_bit(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        op,
+        Bt | Bts | Btr | Btc | Bsf | Bsr | Popcnt | Lzcnt | Tzcnt
+    ) || is_bmi(op)
+}
+
+// BMI instructions are VEX-encoded, so 0x66 does not select their operand size
+pub fn is_bmi(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        op,
+        Andn | Blsr | Blsi | Bzhi | Pdep | Pext | Shlx | Sarx | Rorx
+    )
+}
+
+pub fn encode_bit(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    use OperandSize::*;
+
+    if is_bmi(op) {
+        return encode_bmi(op, oprs, pos);
+    }
+
+    let encoded = match (op, oprs) {
+        // the bit offset is either a register or an imm8
+        (Bt, [base, offset])
+        | (Bts, [base, offset])
+        | (Btr, [base, offset])
+        | (Btc, [base, offset]) => {
+            let (opcode, ext) = match op {
+                Bt => (0xa3, 4),
+                Bts => (0xab, 5),
+                Btr => (0xb3, 6),
+                _ => (0xbb, 7),
+            };
+            let size = common_size(oprs, &[Word, Dword, Qword], op, pos)?;
+            match offset {
+                OperandNode::Register(offset) => {
+                    let reg = reg_code(offset).unwrap();
+                    let bytes = encode_sized(None, &[0x0f, opcode], reg, base, size)
+                        .ok_or_else(|| invalid_operands(op, pos))?;
+                    bytes.into()
+                }
+                OperandNode::Immidiate(value) => {
+                    let imm = u8::try_from(*value).map_err(|_| out_of_range(*value, pos))?;
+                    let bytes = encode_sized(None, &[0x0f, 0xba], ext, base, size)
+                        .ok_or_else(|| invalid_operands(op, pos))?;
+                    let mut encoded = Encoded::from(bytes);
+                    encoded.push_immediate(&[imm]);
+                    encoded
+                }
+                _ => return Err(invalid_operands(op, pos)),
+            }
+        }
+        (_, [OperandNode::Register(dest), src]) => {
+            let (prefix, opcode) = match op {
+                Bsf => (None, 0xbc),
+                Bsr => (None, 0xbd),
+                Popcnt => (Some(0xf3), 0xb8),
+                Lzcnt => (Some(0xf3), 0xbd),
+                _ => (Some(0xf3), 0xbc),
+            };
+            let size = common_size(oprs, &[Word, Dword, Qword], op, pos)?;
+            let bytes = encode_sized(prefix, &[0x0f, opcode], reg_code(dest).unwrap(), src, size)
+                .ok_or_else(|| invalid_operands(op, pos))?;
+            bytes.into()
+        }
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    Ok(encoded)
+}
+
+// all BMI instructions are VEX.LZ and have 32- and 64-bit forms, selected by VEX.W
+fn encode_bmi(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    use OperandSize::*;
+
+    let size = common_size(oprs, &[Dword, Qword], op, pos)?;
+    let is_wide = size == Qword;
+    let code = |opr: &OperandNode| match opr {
+        OperandNode::Register(reg) => Ok(reg_code(reg).unwrap()),
+        _ => Err(invalid_operands(op, pos)),
+    };
+
+    let (pp, map, opcode) = match op {
+        Andn => (Pp::None, Map::M0f38, 0xf2),
+        Pdep => (Pp::F2, Map::M0f38, 0xf5),
+        Pext => (Pp::F3, Map::M0f38, 0xf5),
+        Bzhi => (Pp::None, Map::M0f38, 0xf5),
+        Shlx => (Pp::P66, Map::M0f38, 0xf7),
+        Sarx => (Pp::F3, Map::M0f38, 0xf7),
+        Blsr | Blsi => (Pp::None, Map::M0f38, 0xf3),
+        _ => (Pp::F2, Map::M0f3a, 0xf0),
+    };
+
+    // ModRM.reg, VEX.vvvv and ModRM.rm
+    let (reg, vvvv, rm) = match (op, oprs) {
+        (Andn, [dest, src1, src2]) | (Pdep, [dest, src1, src2]) | (Pext, [dest, src1, src2]) => {
+            (code(dest)?, code(src1)?, src2)
+        }
+        // the index or count is the last operand, but goes in VEX.vvvv
+        (Bzhi, [dest, src, vvvv]) | (Shlx, [dest, src, vvvv]) | (Sarx, [dest, src, vvvv]) => {
+            (code(dest)?, code(vvvv)?, src)
+        }
+        // the destination goes in VEX.vvvv and ModRM.reg extends the opcode
+        (Blsr, [dest, src]) => (1, code(dest)?, src),
+        (Blsi, [dest, src]) => (3, code(dest)?, src),
+        (Rorx, [dest, src, OperandNode::Immidiate(_)]) => (code(dest)?, 0, src),
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    if let OperandNode::Immidiate(_) = rm {
+        return Err(invalid_operands(op, pos));
+    }
+
+    let modrm = encode_modrm(reg, rm, 1).ok_or_else(|| invalid_operands(op, pos))?;
+    let mut bytes = vex_prefix(modrm.r, modrm.x, modrm.b, map, is_wide, vvvv, false, pp);
+    bytes.push(opcode);
+    bytes.extend(modrm.bytes);
+
+    let mut encoded = Encoded::from(bytes);
+    if let [.., OperandNode::Immidiate(value)] = oprs {
+        let imm = u8::try_from(*value).map_err(|_| out_of_range(*value, pos))?;
+        encoded.push_immediate(&[imm]);
+    }
+    Ok(encoded)
+}
+
+// the size shared by the register and memory operands, ignoring immediates
+fn common_size(
+    oprs: &[OperandNode],
+    sizes: &[OperandSize],
+    op: &Mnemonic,
+    pos: &Pos,
+) -> Result<OperandSize, Error> {
+    let mut common = None;
+    for opr in oprs {
+        let size = match opr {
+            OperandNode::Register(reg) => match register_size(reg) {
+                Some(size) if sizes.contains(&size) => size,
+                _ => return Err(unexpected_register(reg, pos)),
+            },
+            OperandNode::Memory(mem) => match mem.size {
+                Some(size) if sizes.contains(&size) => size,
+                Some(actual) => return Err(unexpected_operand_size(actual, pos)),
+                None => continue,
+            },
+            OperandNode::Immidiate(_) => continue,
+            _ => return Err(invalid_operands(op, pos)),
+        };
+
+        match common {
+            Some(common) if common != size => {
+                return Err(match opr {
+                    OperandNode::Register(reg) => unexpected_register(reg, pos),
+                    _ => unexpected_operand_size(size, pos),
+                })
+            }
+            _ => common = Some(size),
+        }
+    }
+    common.ok_or_else(|| Error::new(pos.clone(), ErrorKind::MissingOperandSize))
+}
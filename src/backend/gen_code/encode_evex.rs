@@ -0,0 +1,356 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{invalid_operands, unexpected_register},
+        encode_vex::{is_vex, Map, Pp},
+        modrm::{encode_modrm, reg_code, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize, Rounding},
+};
+
+// the operands an encoding accepts
+#[derive(Clone, Copy)]
+enum Kind {
+    // an xmm, ymm or zmm register, whose width selects EVEX.L'L, or memory of
+    // that width
+    Vector,
+    VectorOrMem,
+    Mem,
+    // an opmask register
+    Mask,
+}
+
+use Kind::*;
+
+// where each operand goes, in the order they are written
+#[derive(Clone, Copy)]
+enum Order {
+    // ModRM.reg, ModRM.rm
+    Rm,
+    // ModRM.rm, ModRM.reg
+    Mr,
+    // ModRM.reg, EVEX.vvvv, ModRM.rm
+    Rvm,
+}
+
+// Every encoding here takes a full vector from memory, or a single element
+// of `elem_size` bytes broadcast to all of them.
+struct Form {
+    pp: Pp,
+    map: Map,
+    w: bool,
+    opcode: u8,
+    order: Order,
+    kinds: &'static [Kind],
+    elem_size: u8,
+    can_broadcast: bool,
+    can_round: bool,
+}
+
+// `elem_size` follows from EVEX.W for every encoding here
+fn form(pp: Pp, map: Map, w: bool, opcode: u8, order: Order, kinds: &'static [Kind]) -> Form {
+    Form {
+        pp,
+        map,
+        w,
+        opcode,
+        order,
+        kinds,
+        elem_size: if w { 8 } else { 4 },
+        can_broadcast: matches!(order, Order::Rvm),
+        can_round: false,
+    }
+}
+
+// floating-point arithmetic can also take a rounding mode
+fn rounded(form: Form) -> Form {
+    Form {
+        can_round: true,
+        ..form
+    }
+}
+
+pub fn has_evex_form(op: &Mnemonic) -> bool {
+    !forms(op).is_empty()
+}
+
+// AVX-512 instructions that also have a VEX form are only EVEX-encoded when
+// they use something VEX cannot express
+pub fn is_evex(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    has_evex_form(op) && (!is_vex(op) || oprs.iter().any(needs_evex))
+}
+
+pub fn encode_evex(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    let (oprs, rounding) = match oprs {
+        [oprs @ .., OperandNode::Rounding(rounding)] => (oprs, Some(*rounding)),
+        oprs => (oprs, None),
+    };
+
+    // only the destination can be masked
+    let mut mask = 0;
+    let mut is_zeroing = false;
+    let mut unmasked = Vec::with_capacity(oprs.len());
+    for (i, opr) in oprs.iter().enumerate() {
+        match opr {
+            OperandNode::Masked {
+                opr,
+                mask: mask_reg,
+                is_zeroing: zeroing,
+            } if i == 0 => {
+                mask = match mask_reg {
+                    Register::K0 => return Err(unexpected_register(mask_reg, pos)),
+                    mask_reg if is_mask(mask_reg) => reg_code(mask_reg).unwrap(),
+                    mask_reg => return Err(unexpected_register(mask_reg, pos)),
+                };
+                // a store can only merge into memory
+                if *zeroing && matches!(opr.as_ref(), OperandNode::Memory(_)) {
+                    return Err(invalid_operands(op, pos));
+                }
+                is_zeroing = *zeroing;
+                unmasked.push(opr.as_ref());
+            }
+            OperandNode::Masked { .. } => return Err(invalid_operands(op, pos)),
+            opr => unmasked.push(opr),
+        }
+    }
+
+    let forms = forms(op);
+    let form = forms
+        .iter()
+        .find(|form| {
+            form.kinds.len() == unmasked.len()
+                && form
+                    .kinds
+                    .iter()
+                    .zip(&unmasked)
+                    .all(|(kind, opr)| accepts(form, *kind, opr))
+        })
+        .ok_or_else(|| invalid_operands(op, pos))?;
+
+    let width = vector_width(form.kinds, &unmasked).ok_or_else(|| invalid_operands(op, pos))?;
+    let broadcast = unmasked.iter().find_map(|opr| match opr {
+        OperandNode::Memory(mem) => mem.broadcast,
+        _ => None,
+    });
+
+    // a broadcast fills the vector with one element, which also scales disp8
+    let disp_scale = match broadcast {
+        Some(count) => {
+            if !form.can_broadcast || count as usize * form.elem_size as usize != width {
+                return Err(invalid_operands(op, pos));
+            }
+            form.elem_size as i32
+        }
+        None => width as i32,
+    };
+
+    // rounding replaces the vector length, which is then always 512 bits
+    let vector_length = match rounding {
+        Some(rounding) => {
+            let has_memory = unmasked
+                .iter()
+                .any(|opr| matches!(opr, OperandNode::Memory(_)));
+            if !form.can_round || width != 64 || has_memory {
+                return Err(invalid_operands(op, pos));
+            }
+            match rounding {
+                Rounding::Nearest => 0b00,
+                Rounding::Down => 0b01,
+                Rounding::Up => 0b10,
+                Rounding::Zero => 0b11,
+            }
+        }
+        None => match width {
+            16 => 0b00,
+            32 => 0b01,
+            _ => 0b10,
+        },
+    };
+
+    let (reg, vvvv, rm) = match (form.order, unmasked.as_slice()) {
+        (Order::Rm, [reg, rm]) => (reg, None, rm),
+        (Order::Mr, [rm, reg]) => (reg, None, rm),
+        (Order::Rvm, [reg, vvvv, rm]) => (reg, Some(vvvv), rm),
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    let reg = register_code(reg);
+    let vvvv = vvvv.map_or(0, |vvvv| register_code(vvvv));
+    let modrm = encode_modrm(reg, rm, disp_scale).ok_or_else(|| invalid_operands(op, pos))?;
+
+    // the fifth bit of a register in ModRM.rm is stored in EVEX.X
+    let x = modrm.x
+        || match rm {
+            OperandNode::Register(_) => register_code(rm) & 16 != 0,
+            _ => false,
+        };
+    let mm = match form.map {
+        Map::M0f => 1,
+        Map::M0f38 => 2,
+        Map::M0f3a => 3,
+    };
+    let b = broadcast.is_some() || rounding.is_some();
+
+    // R, X, B, R', vvvv and V' are stored inverted
+    let mut bytes = vec![
+        0x62,
+        (!modrm.r as u8) << 7
+            | (!x as u8) << 6
+            | (!modrm.b as u8) << 5
+            | ((reg & 16 == 0) as u8) << 4
+            | mm,
+        (form.w as u8) << 7 | (!vvvv & 0xf) << 3 | 1 << 2 | form.pp as u8,
+        (is_zeroing as u8) << 7
+            | vector_length << 5
+            | (b as u8) << 4
+            | ((vvvv & 16 == 0) as u8) << 3
+            | mask,
+    ];
+    bytes.push(form.opcode);
+    bytes.extend(modrm.bytes);
+    Ok(bytes.into())
+}
+
+fn forms(op: &Mnemonic) -> Vec<Form> {
+    use Map::*;
+    use Order::*;
+
+    const RVM: &[Kind] = &[Vector, Vector, VectorOrMem];
+    const LOAD: &[Kind] = &[Vector, VectorOrMem];
+    const STORE: &[Kind] = &[Mem, Vector];
+    let moves = |pp, w, load_opcode, store_opcode| {
+        vec![
+            form(pp, M0f, w, load_opcode, Rm, LOAD),
+            form(pp, M0f, w, store_opcode, Mr, STORE),
+        ]
+    };
+
+    match op {
+        Mnemonic::Vaddps => vec![rounded(form(Pp::None, M0f, false, 0x58, Rvm, RVM))],
+        Mnemonic::Vaddpd => vec![rounded(form(Pp::P66, M0f, true, 0x58, Rvm, RVM))],
+        Mnemonic::Vmulps => vec![rounded(form(Pp::None, M0f, false, 0x59, Rvm, RVM))],
+        Mnemonic::Vmulpd => vec![rounded(form(Pp::P66, M0f, true, 0x59, Rvm, RVM))],
+        Mnemonic::Vpaddd => vec![form(Pp::P66, M0f, false, 0xfe, Rvm, RVM)],
+        Mnemonic::Vpaddq => vec![form(Pp::P66, M0f, true, 0xd4, Rvm, RVM)],
+        Mnemonic::Vpxord => vec![form(Pp::P66, M0f, false, 0xef, Rvm, RVM)],
+        Mnemonic::Vpxorq => vec![form(Pp::P66, M0f, true, 0xef, Rvm, RVM)],
+        Mnemonic::Vfmadd231ps => vec![rounded(form(Pp::P66, M0f38, false, 0xb8, Rvm, RVM))],
+        Mnemonic::Vfmadd231pd => vec![rounded(form(Pp::P66, M0f38, true, 0xb8, Rvm, RVM))],
+        Mnemonic::Vpcmpeqd => vec![form(
+            Pp::P66,
+            M0f,
+            false,
+            0x76,
+            Rvm,
+            &[Mask, Vector, VectorOrMem],
+        )],
+        Mnemonic::Vmovups => moves(Pp::None, false, 0x10, 0x11),
+        Mnemonic::Vmovaps => moves(Pp::None, false, 0x28, 0x29),
+        Mnemonic::Vmovdqu32 => moves(Pp::F3, false, 0x6f, 0x7f),
+        Mnemonic::Vmovdqu64 => moves(Pp::F3, true, 0x6f, 0x7f),
+        Mnemonic::Vmovdqa32 => moves(Pp::P66, false, 0x6f, 0x7f),
+        Mnemonic::Vmovdqa64 => moves(Pp::P66, true, 0x6f, 0x7f),
+        _ => vec![],
+    }
+}
+
+fn accepts(form: &Form, kind: Kind, opr: &OperandNode) -> bool {
+    match (kind, opr) {
+        (Vector, OperandNode::Register(reg)) | (VectorOrMem, OperandNode::Register(reg)) => {
+            vector_size(reg).is_some()
+        }
+        (Mask, OperandNode::Register(reg)) => is_mask(reg),
+        // a broadcast element is sized by the instruction
+        (VectorOrMem, OperandNode::Memory(mem)) => match (mem.broadcast, mem.size) {
+            (Some(_), None) => true,
+            (Some(_), Some(OperandSize::Dword)) => form.elem_size == 4,
+            (Some(_), Some(OperandSize::Qword)) => form.elem_size == 8,
+            (Some(_), Some(_)) => false,
+            (None, size) => size.map_or(true, |size| memory_width(size).is_some()),
+        },
+        (Mem, OperandNode::Memory(mem)) => {
+            mem.broadcast.is_none() && mem.size.map_or(true, |size| memory_width(size).is_some())
+        }
+        _ => false,
+    }
+}
+
+// the width in bytes shared by every vector operand, where unsized and
+// broadcast memory takes the width of the registers
+fn vector_width(kinds: &[Kind], oprs: &[&OperandNode]) -> Option<usize> {
+    let mut width = None;
+    for (kind, opr) in kinds.iter().zip(oprs) {
+        let size = match (kind, opr) {
+            (Vector, OperandNode::Register(reg)) | (VectorOrMem, OperandNode::Register(reg)) => {
+                vector_size(reg)?
+            }
+            (VectorOrMem, OperandNode::Memory(mem)) | (Mem, OperandNode::Memory(mem)) => {
+                match mem.size.and_then(memory_width) {
+                    Some(size) => size,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        match width {
+            None => width = Some(size),
+            Some(width) if width != size => return None,
+            _ => {}
+        }
+    }
+    width
+}
+
+// the width in bytes of vector memory
+fn memory_width(size: OperandSize) -> Option<usize> {
+    match size {
+        OperandSize::Xmmword => Some(16),
+        OperandSize::Ymmword => Some(32),
+        OperandSize::Zmmword => Some(64),
+        _ => None,
+    }
+}
+
+fn needs_evex(opr: &OperandNode) -> bool {
+    match opr {
+        OperandNode::Masked { .. } | OperandNode::Rounding(_) => true,
+        OperandNode::Register(reg) => {
+            is_mask(reg) || vector_size(reg) == Some(64) || reg_code(reg).unwrap_or(0) >= 16
+        }
+        OperandNode::Memory(mem) => {
+            mem.broadcast.is_some() || mem.size == Some(OperandSize::Zmmword)
+        }
+        _ => false,
+    }
+}
+
+fn register_code(opr: &OperandNode) -> u8 {
+    match opr {
+        OperandNode::Register(reg) => reg_code(reg).unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+// the size in bytes of an xmm, ymm or zmm register
+fn vector_size(reg: &Register) -> Option<usize> {
+    use Register::*;
+    let size = match reg {
+        Xmm0 | Xmm1 | Xmm2 | Xmm3 | Xmm4 | Xmm5 | Xmm6 | Xmm7 | Xmm8 | Xmm9 | Xmm10 | Xmm11
+        | Xmm12 | Xmm13 | Xmm14 | Xmm15 | Xmm16 | Xmm17 | Xmm18 | Xmm19 | Xmm20 | Xmm21 | Xmm22
+        | Xmm23 | Xmm24 | Xmm25 | Xmm26 | Xmm27 | Xmm28 | Xmm29 | Xmm30 | Xmm31 => 16,
+        Ymm0 | Ymm1 | Ymm2 | Ymm3 | Ymm4 | Ymm5 | Ymm6 | Ymm7 | Ymm8 | Ymm9 | Ymm10 | Ymm11
+        | Ymm12 | Ymm13 | Ymm14 | Ymm15 | Ymm16 | Ymm17 | Ymm18 | Ymm19 | Ymm20 | Ymm21 | Ymm22
+        | Ymm23 | Ymm24 | Ymm25 | Ymm26 | Ymm27 | Ymm28 | Ymm29 | Ymm30 | Ymm31 => 32,
+        Zmm0 | Zmm1 | Zmm2 | Zmm3 | Zmm4 | Zmm5 | Zmm6 | Zmm7 | Zmm8 | Zmm9 | Zmm10 | Zmm11
+        | Zmm12 | Zmm13 | Zmm14 | Zmm15 | Zmm16 | Zmm17 | Zmm18 | Zmm19 | Zmm20 | Zmm21 | Zmm22
+        | Zmm23 | Zmm24 | Zmm25 | Zmm26 | Zmm27 | Zmm28 | Zmm29 | Zmm30 | Zmm31 => 64,
+        _ => return None,
+    };
+    Some(size)
+}
+
+fn is_mask(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, K0 | K1 | K2 | K3 | K4 | K5 | K6 | K7)
+}
@@ -0,0 +1,83 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::mnemonic::Mnemonic;
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            invalid_operands, operand_size, out_of_range, unexpected_operand_size,
+            unexpected_register,
+        },
+        modrm::{encode_legacy, encode_sized, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// nops, traps, stack frames, and pushes and pops of memory
+pub fn is_flow(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    use Mnemonic::*;
+    match op {
+        Nop | Int3 | Ud2 | Endbr64 | Leave | Enter => true,
+        // registers and immediates are left to x86asm
+        Push | Pop => matches!(oprs, [OperandNode::Memory(_)]),
+        _ => false,
+    }
+}
+
+pub fn encode_flow(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    use OperandSize::*;
+    let bytes = match (op, oprs) {
+        (Nop, []) => vec![0x90],
+        // the multi-byte nop, whose length is up to its operand
+        (Nop, [opr]) => {
+            let size = rm_size(opr, &[Word, Dword, Qword], Dword, op, pos)?;
+            encode_sized(None, &[0x0f, 0x1f], 0, opr, size)
+                .ok_or_else(|| invalid_operands(op, pos))?
+        }
+        (Int3, []) => vec![0xcc],
+        (Ud2, []) => vec![0x0f, 0x0b],
+        (Endbr64, []) => vec![0xf3, 0x0f, 0x1e, 0xfa],
+        (Leave, []) => vec![0xc9],
+        // the size of the frame and its nesting level
+        (Enter, [OperandNode::Immidiate(size), OperandNode::Immidiate(level)]) => {
+            let size = u16::try_from(*size).map_err(|_| out_of_range(*size, pos))?;
+            let level = u8::try_from(*level).map_err(|_| out_of_range(*level, pos))?;
+            let mut bytes = vec![0xc8];
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes.push(level);
+            bytes
+        }
+        // the stack only takes 16- and 64-bit operands, where the latter need no REX.W
+        (Push, [mem]) | (Pop, [mem]) => {
+            let (opcode, ext) = if *op == Push { (0xff, 6) } else { (0x8f, 0) };
+            let size = rm_size(mem, &[Word, Qword], Qword, op, pos)?;
+            let mut bytes = if size == Word { vec![0x66] } else { vec![] };
+            bytes.extend(
+                encode_legacy(&[opcode], ext, false, mem)
+                    .ok_or_else(|| invalid_operands(op, pos))?,
+            );
+            bytes
+        }
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    Ok(bytes.into())
+}
+
+// the size of a register or memory operand, which is `default` for unsized memory
+fn rm_size(
+    rm: &OperandNode,
+    sizes: &[OperandSize],
+    default: OperandSize,
+    op: &Mnemonic,
+    pos: &Pos,
+) -> Result<OperandSize, Error> {
+    match (rm, operand_size(rm)) {
+        (_, Some(size)) if sizes.contains(&size) => Ok(size),
+        (OperandNode::Memory(_), None) => Ok(default),
+        (OperandNode::Register(reg), _) => Err(unexpected_register(reg, pos)),
+        (OperandNode::Memory(_), Some(actual)) => Err(unexpected_operand_size(actual, pos)),
+        _ => Err(invalid_operands(op, pos)),
+    }
+}
@@ -0,0 +1,49 @@
+use x86asm::instruction::mnemonic::Mnemonic;
+
+use crate::{
+    backend::gen_code::encode_string::{is_comparing_string, is_string},
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
+    frontend::{lexer::token::Prefix, parser::node::OperandNode},
+};
+
+// `rep` repeats any string instruction, while `repe` and `repne` also test
+// the flags and so are only meaningful for cmps and scas. `lock` makes a
+// read-modify-write of memory atomic.
+pub fn encode_prefix(
+    prefix: Prefix,
+    op: &Mnemonic,
+    oprs: &[OperandNode],
+    pos: &Pos,
+) -> Result<u8, Error> {
+    let byte = match prefix {
+        Prefix::Lock if is_lockable(op, oprs) => 0xf0,
+        Prefix::Rep if is_string(op, oprs) => 0xf3,
+        Prefix::Repe if is_string(op, oprs) && is_comparing_string(op) => 0xf3,
+        Prefix::Repne if is_string(op, oprs) && is_comparing_string(op) => 0xf2,
+        _ => {
+            return Err(Error::new(
+                pos.clone(),
+                ErrorKind::InvalidPrefix {
+                    prefix,
+                    mnemonic: op.clone(),
+                },
+            ))
+        }
+    };
+    Ok(byte)
+}
+
+fn is_lockable(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    use Mnemonic::*;
+    let is_mem_dest = matches!(oprs.first(), Some(OperandNode::Memory(_)));
+    match op {
+        Adc | Add | And | Btc | Btr | Bts | Cmpxchg | Cmpxchg8b | Cmpxchg16b | Dec | Inc | Neg
+        | Not | Or | Sbb | Sub | Xadd | Xor => is_mem_dest,
+        // xchg with memory is atomic anyway, but can still be written with lock
+        Xchg => oprs.iter().any(|opr| matches!(opr, OperandNode::Memory(_))),
+        _ => false,
+    }
+}
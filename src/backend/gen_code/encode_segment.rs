@@ -0,0 +1,158 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            invalid_operands, out_of_range, register_size, unexpected_operand_size,
+            unexpected_register,
+        },
+        mode::Mode,
+        modrm::{encode_legacy, reg_code, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{DispNode, OperandNode, OperandSize},
+};
+
+// far jumps, calls and returns, and moves, pushes and pops of segment registers
+pub fn is_segment(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    let has_segment_register = oprs.iter().any(|opr| match opr {
+        OperandNode::Register(reg) => is_segment_register(reg),
+        _ => false,
+    });
+    match op {
+        Mnemonic::Retf => true,
+        Mnemonic::Mov | Mnemonic::Push | Mnemonic::Pop => has_segment_register,
+        Mnemonic::Jmp | Mnemonic::Call => matches!(oprs, [opr] if is_far(opr)),
+        _ => false,
+    }
+}
+
+pub fn encode_segment(
+    op: &Mnemonic,
+    oprs: &[OperandNode],
+    mode: Mode,
+    pos: &Pos,
+) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    use Register::*;
+    let bytes = match (op, oprs) {
+        (Retf, []) => vec![0xcb],
+        (Retf, [OperandNode::Immidiate(value)]) => {
+            let value = u16::try_from(*value).map_err(|_| out_of_range(*value, pos))?;
+            let mut bytes = vec![0xca];
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        }
+        // 64-bit mode only has indirect far jumps and calls
+        (Jmp, [OperandNode::FarPointer { .. }]) | (Call, [OperandNode::FarPointer { .. }])
+            if mode == Mode::Bits64 =>
+        {
+            return Err(invalid_operands(op, pos))
+        }
+        (Jmp, [OperandNode::FarPointer { segment, offset }]) => {
+            encode_far_pointer(0xea, *segment, offset, mode, pos)?
+        }
+        (Call, [OperandNode::FarPointer { segment, offset }]) => {
+            encode_far_pointer(0x9a, *segment, offset, mode, pos)?
+        }
+        (Jmp, [mem]) => {
+            encode_legacy(&[0xff], 5, false, mem).ok_or_else(|| invalid_operands(op, pos))?
+        }
+        (Call, [mem]) => {
+            encode_legacy(&[0xff], 3, false, mem).ok_or_else(|| invalid_operands(op, pos))?
+        }
+        (Push, [OperandNode::Register(Fs)]) => vec![0x0f, 0xa0],
+        (Push, [OperandNode::Register(Gs)]) => vec![0x0f, 0xa8],
+        (Pop, [OperandNode::Register(Fs)]) => vec![0x0f, 0xa1],
+        (Pop, [OperandNode::Register(Gs)]) => vec![0x0f, 0xa9],
+        // the others can only be pushed and popped outside 64-bit mode, and cs
+        // can only be popped by a far return
+        (Push, [OperandNode::Register(reg)]) | (Pop, [OperandNode::Register(reg)])
+            if mode == Mode::Bits64 || (*op == Pop && *reg == Cs) =>
+        {
+            return Err(unexpected_register(reg, pos))
+        }
+        (Push, [OperandNode::Register(reg)]) => vec![(reg_code(reg).unwrap() << 3) | 0x06],
+        (Pop, [OperandNode::Register(reg)]) => vec![(reg_code(reg).unwrap() << 3) | 0x07],
+        (Mov, [OperandNode::Register(dest), src]) if is_segment_register(dest) => {
+            // cs can only be loaded by a far jump, call or return
+            if *dest == Cs {
+                return Err(unexpected_register(dest, pos));
+            }
+            let is_wide = check_segment_operand(src, op, pos)?;
+            encode_legacy(&[0x8e], reg_code(dest).unwrap(), is_wide, src).unwrap()
+        }
+        (Mov, [dest, OperandNode::Register(src)]) if is_segment_register(src) => {
+            let is_wide = check_segment_operand(dest, op, pos)?;
+            let mut bytes = Vec::new();
+            if let OperandNode::Register(dest) = dest {
+                if register_size(dest) == Some(OperandSize::Word) {
+                    bytes.push(0x66);
+                }
+            }
+            bytes.extend(encode_legacy(&[0x8c], reg_code(src).unwrap(), is_wide, dest).unwrap());
+            bytes
+        }
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    Ok(bytes.into())
+}
+
+// The offset comes before the segment, so a label offset is relocated six
+// bytes from the end of the instruction, or four in 16-bit mode where the
+// offset is 16-bit.
+fn encode_far_pointer(
+    opcode: u8,
+    segment: i64,
+    offset: &DispNode,
+    mode: Mode,
+    pos: &Pos,
+) -> Result<Vec<u8>, Error> {
+    let segment = u16::try_from(segment).map_err(|_| out_of_range(segment, pos))?;
+    let offset = match offset {
+        DispNode::Immediate(offset) => *offset,
+        DispNode::Label { .. } => 0,
+    };
+
+    let mut bytes = vec![opcode];
+    if mode == Mode::Bits16 {
+        let offset = u16::try_from(offset).map_err(|_| out_of_range(offset, pos))?;
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    } else {
+        let offset = u32::try_from(offset).map_err(|_| out_of_range(offset, pos))?;
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    }
+    bytes.extend_from_slice(&segment.to_le_bytes());
+    Ok(bytes)
+}
+
+// the general-purpose register or memory moved to or from a segment register,
+// returning whether it needs REX.W
+fn check_segment_operand(opr: &OperandNode, op: &Mnemonic, pos: &Pos) -> Result<bool, Error> {
+    use OperandSize::*;
+    match opr {
+        OperandNode::Register(reg) => match register_size(reg) {
+            Some(Word) | Some(Dword) => Ok(false),
+            Some(Qword) => Ok(true),
+            _ => Err(unexpected_register(reg, pos)),
+        },
+        OperandNode::Memory(mem) if matches!(mem.size, None | Some(Word)) => Ok(false),
+        OperandNode::Memory(mem) => Err(unexpected_operand_size(mem.size.unwrap(), pos)),
+        _ => Err(invalid_operands(op, pos)),
+    }
+}
+
+fn is_far(opr: &OperandNode) -> bool {
+    match opr {
+        OperandNode::FarPointer { .. } => true,
+        OperandNode::Memory(mem) => mem.size == Some(OperandSize::Fword),
+        _ => false,
+    }
+}
+
+pub fn is_segment_register(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, Es | Cs | Ss | Ds | Fs | Gs)
+}
@@ -0,0 +1,200 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{invalid_operands, register_size},
+        modrm::{encode_modrm, reg_code, rex, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// the operands an encoding accepts
+#[derive(Clone, Copy)]
+enum Kind {
+    Xmm,
+    // an xmm register, or memory of the given size
+    XmmOrMem(OperandSize),
+    Mem(OperandSize),
+    Gpr(OperandSize),
+    GprOrMem(OperandSize),
+}
+
+use Kind::*;
+use OperandSize::*;
+
+// `prefix 0F opcode /r` where ModRM.reg holds the first operand, unless `is_store` is set
+struct Form {
+    prefix: Option<u8>,
+    rex_w: bool,
+    opcode: u8,
+    is_store: bool,
+    dest: Kind,
+    src: Kind,
+}
+
+fn load(prefix: Option<u8>, opcode: u8, dest: Kind, src: Kind) -> Form {
+    Form {
+        prefix,
+        rex_w: false,
+        opcode,
+        is_store: false,
+        dest,
+        src,
+    }
+}
+
+fn store(prefix: Option<u8>, opcode: u8, dest: Kind, src: Kind) -> Form {
+    Form {
+        prefix,
+        rex_w: false,
+        opcode,
+        is_store: true,
+        dest,
+        src,
+    }
+}
+
+fn wide(form: Form) -> Form {
+    Form {
+        rex_w: true,
+        ..form
+    }
+}
+
+pub fn is_sse(op: &Mnemonic) -> bool {
+    !forms(op).is_empty()
+}
+
+// only called for the two-operand form of movsd, the string instruction has none
+pub fn encode_sse(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    let (dest, src) = match oprs {
+        [dest, src] => (dest, src),
+        _ => return Err(invalid_operands(op, pos)),
+    };
+
+    let forms = forms(op);
+    let form = forms
+        .iter()
+        .find(|form| accepts(form.dest, dest) && accepts(form.src, src))
+        .ok_or_else(|| invalid_operands(op, pos))?;
+
+    let (reg, rm) = if form.is_store {
+        (src, dest)
+    } else {
+        (dest, src)
+    };
+    let reg = match reg {
+        OperandNode::Register(reg) => reg_code(reg).unwrap(),
+        _ => unreachable!(),
+    };
+    let modrm = encode_modrm(reg, rm, 1).ok_or_else(|| invalid_operands(op, pos))?;
+
+    let mut bytes = Vec::new();
+    bytes.extend(form.prefix);
+    if form.rex_w || modrm.r || modrm.x || modrm.b {
+        bytes.push(rex(form.rex_w, modrm.r, modrm.x, modrm.b));
+    }
+    bytes.push(0x0f);
+    bytes.push(form.opcode);
+    bytes.extend(modrm.bytes);
+    Ok(bytes.into())
+}
+
+fn forms(op: &Mnemonic) -> Vec<Form> {
+    const F2: Option<u8> = Some(0xf2);
+    const F3: Option<u8> = Some(0xf3);
+    const P66: Option<u8> = Some(0x66);
+    const NP: Option<u8> = None;
+
+    match op {
+        Mnemonic::Movss => vec![
+            load(F3, 0x10, Xmm, XmmOrMem(Dword)),
+            store(F3, 0x11, Mem(Dword), Xmm),
+        ],
+        Mnemonic::Movsd => vec![
+            load(F2, 0x10, Xmm, XmmOrMem(Qword)),
+            store(F2, 0x11, Mem(Qword), Xmm),
+        ],
+        Mnemonic::Movaps => vec![
+            load(NP, 0x28, Xmm, XmmOrMem(Xmmword)),
+            store(NP, 0x29, Mem(Xmmword), Xmm),
+        ],
+        Mnemonic::Movups => vec![
+            load(NP, 0x10, Xmm, XmmOrMem(Xmmword)),
+            store(NP, 0x11, Mem(Xmmword), Xmm),
+        ],
+        Mnemonic::Movq => vec![
+            load(F3, 0x7e, Xmm, XmmOrMem(Qword)),
+            store(P66, 0xd6, Mem(Qword), Xmm),
+            wide(load(P66, 0x6e, Xmm, Gpr(Qword))),
+            wide(store(P66, 0x7e, Gpr(Qword), Xmm)),
+        ],
+        Mnemonic::Movd => vec![
+            load(P66, 0x6e, Xmm, GprOrMem(Dword)),
+            store(P66, 0x7e, GprOrMem(Dword), Xmm),
+        ],
+        Mnemonic::Addss => vec![load(F3, 0x58, Xmm, XmmOrMem(Dword))],
+        Mnemonic::Addsd => vec![load(F2, 0x58, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Subsd => vec![load(F2, 0x5c, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Mulsd => vec![load(F2, 0x59, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Divsd => vec![load(F2, 0x5e, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Sqrtsd => vec![load(F2, 0x51, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Ucomisd => vec![load(P66, 0x2e, Xmm, XmmOrMem(Qword))],
+        Mnemonic::Comisd => vec![load(P66, 0x2f, Xmm, XmmOrMem(Qword))],
+        // an unsized memory source is converted as a 32-bit integer
+        Mnemonic::Cvtsi2sd => vec![
+            load(F2, 0x2a, Xmm, GprOrMem(Dword)),
+            wide(load(F2, 0x2a, Xmm, GprOrMem(Qword))),
+        ],
+        Mnemonic::Cvttsd2si => vec![
+            load(F2, 0x2c, Gpr(Dword), XmmOrMem(Qword)),
+            wide(load(F2, 0x2c, Gpr(Qword), XmmOrMem(Qword))),
+        ],
+        Mnemonic::Cvtss2sd => vec![load(F3, 0x5a, Xmm, XmmOrMem(Dword))],
+        Mnemonic::Pxor => vec![load(P66, 0xef, Xmm, XmmOrMem(Xmmword))],
+        Mnemonic::Xorps => vec![load(NP, 0x57, Xmm, XmmOrMem(Xmmword))],
+        Mnemonic::Andpd => vec![load(P66, 0x54, Xmm, XmmOrMem(Xmmword))],
+        _ => vec![],
+    }
+}
+
+fn accepts(kind: Kind, opr: &OperandNode) -> bool {
+    match (kind, opr) {
+        (Xmm, OperandNode::Register(reg)) | (XmmOrMem(_), OperandNode::Register(reg)) => {
+            is_xmm(reg)
+        }
+        (Gpr(size), OperandNode::Register(reg)) | (GprOrMem(size), OperandNode::Register(reg)) => {
+            register_size(reg) == Some(size)
+        }
+        // unsized memory takes the size of the encoding
+        (XmmOrMem(size), OperandNode::Memory(mem))
+        | (Mem(size), OperandNode::Memory(mem))
+        | (GprOrMem(size), OperandNode::Memory(mem)) => {
+            mem.size.is_none() || mem.size == Some(size)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_xmm(reg: &Register) -> bool {
+    use Register::*;
+    matches!(
+        reg,
+        Xmm0 | Xmm1
+            | Xmm2
+            | Xmm3
+            | Xmm4
+            | Xmm5
+            | Xmm6
+            | Xmm7
+            | Xmm8
+            | Xmm9
+            | Xmm10
+            | Xmm11
+            | Xmm12
+            | Xmm13
+            | Xmm14
+            | Xmm15
+    )
+}
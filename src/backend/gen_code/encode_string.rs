@@ -0,0 +1,57 @@
+use x86asm::instruction::mnemonic::Mnemonic;
+
+use crate::frontend::parser::node::{OperandNode, OperandSize};
+
+// `movsd` with operands is the SSE move instead
+pub fn is_string(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    oprs.is_empty() && string_form(op).is_some()
+}
+
+// cmps and scas compare, so they stop on the flags when repeated
+pub fn is_comparing_string(op: &Mnemonic) -> bool {
+    matches!(string_form(op), Some((0xa6, _)) | Some((0xae, _)))
+}
+
+pub fn encode_string(op: &Mnemonic) -> Vec<u8> {
+    let (opcode, size) = string_form(op).unwrap();
+    match size {
+        OperandSize::Byte => vec![opcode],
+        OperandSize::Word => vec![0x66, opcode + 1],
+        OperandSize::Dword => vec![opcode + 1],
+        OperandSize::Qword => vec![0x48, opcode + 1],
+        OperandSize::Fword => unreachable!(),
+    }
+}
+
+pub fn string_size(op: &Mnemonic) -> Option<OperandSize> {
+    string_form(op).map(|(_, size)| size)
+}
+
+// the opcode of the byte form, which is one less than that of the wider forms
+fn string_form(op: &Mnemonic) -> Option<(u8, OperandSize)> {
+    use OperandSize::*;
+    let form = match op {
+        Mnemonic::Movsb => (0xa4, Byte),
+        Mnemonic::Movsw => (0xa4, Word),
+        Mnemonic::Movsd => (0xa4, Dword),
+        Mnemonic::Movsq => (0xa4, Qword),
+        Mnemonic::Cmpsb => (0xa6, Byte),
+        Mnemonic::Cmpsw => (0xa6, Word),
+        Mnemonic::Cmpsd => (0xa6, Dword),
+        Mnemonic::Cmpsq => (0xa6, Qword),
+        Mnemonic::Stosb => (0xaa, Byte),
+        Mnemonic::Stosw => (0xaa, Word),
+        Mnemonic::Stosd => (0xaa, Dword),
+        Mnemonic::Stosq => (0xaa, Qword),
+        Mnemonic::Lodsb => (0xac, Byte),
+        Mnemonic::Lodsw => (0xac, Word),
+        Mnemonic::Lodsd => (0xac, Dword),
+        Mnemonic::Lodsq => (0xac, Qword),
+        Mnemonic::Scasb => (0xae, Byte),
+        Mnemonic::Scasw => (0xae, Word),
+        Mnemonic::Scasd => (0xae, Dword),
+        Mnemonic::Scasq => (0xae, Qword),
+        _ => return None,
+    };
+    Some(form)
+}
@@ -0,0 +1,111 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            invalid_operands, register_size, unexpected_operand_size, unexpected_register,
+        },
+        modrm::{encode_legacy, encode_mem, reg_code, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// atomic read-modify-writes, fences, prefetches, and the instructions that
+// read the time stamp counter or identify the processor
+pub fn is_sync(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    use Mnemonic::*;
+    match op {
+        // exchanges between registers are left to x86asm
+        Xchg => oprs.iter().any(|opr| matches!(opr, OperandNode::Memory(_))),
+        Cmpxchg | Cmpxchg8b | Cmpxchg16b | Xadd | Mfence | Lfence | Sfence | Pause | Rdtsc
+        | Rdtscp | Cpuid | Prefetcht0 | Prefetcht1 | Prefetcht2 | Prefetchnta | Prefetchw => true,
+        _ => false,
+    }
+}
+
+pub fn encode_sync(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    use OperandSize::*;
+    let bytes = match (op, oprs) {
+        (Mfence, []) => vec![0x0f, 0xae, 0xf0],
+        (Lfence, []) => vec![0x0f, 0xae, 0xe8],
+        (Sfence, []) => vec![0x0f, 0xae, 0xf8],
+        (Pause, []) => vec![0xf3, 0x90],
+        (Rdtsc, []) => vec![0x0f, 0x31],
+        (Rdtscp, []) => vec![0x0f, 0x01, 0xf9],
+        (Cpuid, []) => vec![0x0f, 0xa2],
+        (Cmpxchg, [dest, OperandNode::Register(src)]) => {
+            encode_rm_reg(&[0x0f, 0xb0], dest, src, op, pos)?
+        }
+        (Xadd, [dest, OperandNode::Register(src)]) => {
+            encode_rm_reg(&[0x0f, 0xc0], dest, src, op, pos)?
+        }
+        // the memory operand of xchg can come either first or second
+        (Xchg, [mem @ OperandNode::Memory(_), OperandNode::Register(reg)])
+        | (Xchg, [OperandNode::Register(reg), mem @ OperandNode::Memory(_)]) => {
+            encode_rm_reg(&[0x86], mem, reg, op, pos)?
+        }
+        (Cmpxchg8b, [mem]) => encode_mem(&[0x0f, 0xc7], 1, false, mem, Some(Qword), op, pos)?,
+        // there is no size keyword for 16 bytes
+        (Cmpxchg16b, [mem]) => encode_mem(&[0x0f, 0xc7], 1, true, mem, None, op, pos)?,
+        (Prefetchnta, [mem]) => encode_mem(&[0x0f, 0x18], 0, false, mem, Some(Byte), op, pos)?,
+        (Prefetcht0, [mem]) => encode_mem(&[0x0f, 0x18], 1, false, mem, Some(Byte), op, pos)?,
+        (Prefetcht1, [mem]) => encode_mem(&[0x0f, 0x18], 2, false, mem, Some(Byte), op, pos)?,
+        (Prefetcht2, [mem]) => encode_mem(&[0x0f, 0x18], 3, false, mem, Some(Byte), op, pos)?,
+        (Prefetchw, [mem]) => encode_mem(&[0x0f, 0x0d], 1, false, mem, Some(Byte), op, pos)?,
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    Ok(bytes.into())
+}
+
+// `op r/m, reg`, where the opcode of the byte form is one less than that of
+// the wider forms
+fn encode_rm_reg(
+    opcode: &[u8],
+    rm: &OperandNode,
+    reg: &Register,
+    op: &Mnemonic,
+    pos: &Pos,
+) -> Result<Vec<u8>, Error> {
+    use OperandSize::*;
+    let size = register_size(reg).ok_or_else(|| unexpected_register(reg, pos))?;
+    match rm {
+        OperandNode::Register(rm) if register_size(rm) != Some(size) => {
+            return Err(unexpected_register(rm, pos));
+        }
+        OperandNode::Memory(mem) => match mem.size {
+            Some(actual) if actual != size => return Err(unexpected_operand_size(actual, pos)),
+            _ => {}
+        },
+        OperandNode::Register(_) => {}
+        _ => return Err(invalid_operands(op, pos)),
+    }
+
+    let mut opcode = opcode.to_vec();
+    if size != Byte {
+        *opcode.last_mut().unwrap() += 1;
+    }
+
+    let mut bytes = Vec::new();
+    if size == Word {
+        bytes.push(0x66);
+    }
+    let mut encoded = encode_legacy(&opcode, reg_code(reg).unwrap(), size == Qword, rm)
+        .ok_or_else(|| invalid_operands(op, pos))?;
+
+    // spl, bpl, sil and dil need a REX prefix, even without any of its bits
+    let has_rex = encoded[0] & 0xf0 == 0x40;
+    let is_new_byte_rm = matches!(rm, OperandNode::Register(rm) if is_new_byte_register(rm));
+    if (is_new_byte_register(reg) || is_new_byte_rm) && !has_rex {
+        encoded.insert(0, 0x40);
+    }
+
+    bytes.extend(encoded);
+    Ok(bytes)
+}
+
+fn is_new_byte_register(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, Spl | Bpl | Sil | Dil)
+}
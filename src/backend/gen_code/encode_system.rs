@@ -0,0 +1,153 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            invalid_operands, operand_size, out_of_range, register_size, unexpected_register,
+        },
+        modrm::{encode_legacy, encode_mem, reg_code, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// privileged and system instructions, and moves to or from control and debug registers
+pub fn is_system(op: &Mnemonic, oprs: &[OperandNode]) -> bool {
+    use Mnemonic::*;
+    match op {
+        Mov => oprs.iter().any(|opr| match opr {
+            OperandNode::Register(reg) => is_control(reg) || is_debug(reg),
+            _ => false,
+        }),
+        Cli | Sti | Rdmsr | Wrmsr | Iretq | Sysret | Sysretq | Swapgs | Wbinvd | Lgdt | Lidt
+        | Ltr | Invlpg | Xsave | Xrstor | In | Out | Int => true,
+        _ => false,
+    }
+}
+
+pub fn encode_system(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    use Mnemonic::*;
+    let bytes = match (op, oprs) {
+        (Cli, []) => vec![0xfa],
+        (Sti, []) => vec![0xfb],
+        (Rdmsr, []) => vec![0x0f, 0x32],
+        (Wrmsr, []) => vec![0x0f, 0x30],
+        (Iretq, []) => vec![0x48, 0xcf],
+        (Sysret, []) => vec![0x0f, 0x07],
+        (Sysretq, []) => vec![0x48, 0x0f, 0x07],
+        (Swapgs, []) => vec![0x0f, 0x01, 0xf8],
+        (Wbinvd, []) => vec![0x0f, 0x09],
+        (Lgdt, [mem]) => encode_mem(&[0x0f, 0x01], 2, false, mem, None, op, pos)?,
+        (Lidt, [mem]) => encode_mem(&[0x0f, 0x01], 3, false, mem, None, op, pos)?,
+        // invlpg takes memory of any size
+        (Invlpg, [mem]) => {
+            let size = operand_size(mem);
+            encode_mem(&[0x0f, 0x01], 7, false, mem, size, op, pos)?
+        }
+        (Xsave, [mem]) => encode_mem(&[0x0f, 0xae], 4, false, mem, None, op, pos)?,
+        (Xrstor, [mem]) => encode_mem(&[0x0f, 0xae], 5, false, mem, None, op, pos)?,
+        (Ltr, [opr]) => {
+            if !matches!(operand_size(opr), None | Some(OperandSize::Word)) {
+                return Err(unexpected(opr, op, pos));
+            }
+            if let OperandNode::Register(reg) = opr {
+                if register_size(reg).is_none() {
+                    return Err(unexpected_register(reg, pos));
+                }
+            }
+            encode_legacy(&[0x0f, 0x00], 3, false, opr).ok_or_else(|| invalid_operands(op, pos))?
+        }
+        (Mov, [dest, src]) => encode_mov(dest, src, op, pos)?,
+        (In, [OperandNode::Register(dest), port]) => {
+            let mut bytes = accumulator_prefix(dest, pos)?;
+            match port {
+                OperandNode::Register(Register::Dx) => bytes.push(0xec | wide(dest)),
+                OperandNode::Immidiate(port) => {
+                    bytes.push(0xe4 | wide(dest));
+                    bytes.push(imm8(*port, pos)?);
+                }
+                port => return Err(unexpected(port, op, pos)),
+            }
+            bytes
+        }
+        (Out, [port, OperandNode::Register(src)]) => {
+            let mut bytes = accumulator_prefix(src, pos)?;
+            match port {
+                OperandNode::Register(Register::Dx) => bytes.push(0xee | wide(src)),
+                OperandNode::Immidiate(port) => {
+                    bytes.push(0xe6 | wide(src));
+                    bytes.push(imm8(*port, pos)?);
+                }
+                port => return Err(unexpected(port, op, pos)),
+            }
+            bytes
+        }
+        (Int, [OperandNode::Immidiate(vector)]) => vec![0xcd, imm8(*vector, pos)?],
+        _ => return Err(invalid_operands(op, pos)),
+    };
+    Ok(bytes.into())
+}
+
+// `mov cr, r64`, `mov r64, cr` and the same for debug registers
+fn encode_mov(
+    dest: &OperandNode,
+    src: &OperandNode,
+    op: &Mnemonic,
+    pos: &Pos,
+) -> Result<Vec<u8>, Error> {
+    let (opcode, special, gpr) = match (dest, src) {
+        (OperandNode::Register(dest), src) if is_control(dest) => (0x22, dest, src),
+        (OperandNode::Register(dest), src) if is_debug(dest) => (0x23, dest, src),
+        (dest, OperandNode::Register(src)) if is_control(src) => (0x20, src, dest),
+        (dest, OperandNode::Register(src)) if is_debug(src) => (0x21, src, dest),
+        _ => unreachable!(),
+    };
+
+    // the other operand is always a 64-bit general-purpose register
+    match gpr {
+        OperandNode::Register(reg) if register_size(reg) == Some(OperandSize::Qword) => {}
+        OperandNode::Register(reg) => return Err(unexpected_register(reg, pos)),
+        _ => return Err(invalid_operands(op, pos)),
+    }
+
+    let reg = reg_code(special).unwrap();
+    Ok(encode_legacy(&[0x0f, opcode], reg, false, gpr).unwrap())
+}
+
+// in and out transfer through al, ax or eax
+fn accumulator_prefix(reg: &Register, pos: &Pos) -> Result<Vec<u8>, Error> {
+    match reg {
+        Register::Al | Register::Eax => Ok(vec![]),
+        Register::Ax => Ok(vec![0x66]),
+        reg => Err(unexpected_register(reg, pos)),
+    }
+}
+
+// the low bit of in and out opcodes selects a 16- or 32-bit transfer
+fn wide(reg: &Register) -> u8 {
+    (*reg != Register::Al) as u8
+}
+
+fn imm8(value: i64, pos: &Pos) -> Result<u8, Error> {
+    u8::try_from(value).map_err(|_| out_of_range(value, pos))
+}
+
+fn is_control(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, Cr0 | Cr2 | Cr3 | Cr4 | Cr8)
+}
+
+fn is_debug(reg: &Register) -> bool {
+    use Register::*;
+    matches!(reg, Dr0 | Dr1 | Dr2 | Dr3 | Dr4 | Dr5 | Dr6 | Dr7)
+}
+
+// reports a register by name, and anything else as invalid operands
+fn unexpected(opr: &OperandNode, op: &Mnemonic, pos: &Pos) -> Error {
+    match opr {
+        OperandNode::Register(reg) => unexpected_register(reg, pos),
+        _ => invalid_operands(op, pos),
+    }
+}
@@ -0,0 +1,283 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{invalid_operands, register_size},
+        encode_sse::is_xmm,
+        modrm::{encode_modrm, reg_code, Encoded},
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{OperandNode, OperandSize},
+};
+
+// the operands an encoding accepts
+#[derive(Clone, Copy)]
+enum Kind {
+    // an xmm or ymm register, whose width selects VEX.L, or memory of that width
+    Vector,
+    VectorOrMem,
+    Mem,
+    // an xmm register, or memory of the given size, regardless of VEX.L
+    XmmOrMem(OperandSize),
+    Gpr(OperandSize),
+}
+
+use Kind::*;
+
+// where each operand goes, in the order they are written
+#[derive(Clone, Copy)]
+enum Order {
+    // no operands
+    Zo,
+    // ModRM.reg, ModRM.rm
+    Rm,
+    // ModRM.rm, ModRM.reg
+    Mr,
+    // ModRM.reg, VEX.vvvv, ModRM.rm
+    Rvm,
+}
+
+#[derive(Clone, Copy)]
+pub enum Pp {
+    None,
+    P66,
+    F3,
+    F2,
+}
+
+#[derive(Clone, Copy)]
+pub enum Map {
+    M0f,
+    M0f38,
+    M0f3a,
+}
+
+struct Form {
+    pp: Pp,
+    map: Map,
+    w: bool,
+    opcode: u8,
+    order: Order,
+    kinds: Vec<Kind>,
+}
+
+fn form(pp: Pp, map: Map, w: bool, opcode: u8, order: Order, kinds: &[Kind]) -> Form {
+    Form {
+        pp,
+        map,
+        w,
+        opcode,
+        order,
+        kinds: kinds.to_vec(),
+    }
+}
+
+pub fn is_vex(op: &Mnemonic) -> bool {
+    !forms(op).is_empty()
+}
+
+pub fn encode_vex(op: &Mnemonic, oprs: &[OperandNode], pos: &Pos) -> Result<Encoded, Error> {
+    let forms = forms(op);
+    let form = forms
+        .iter()
+        .find(|form| {
+            form.kinds.len() == oprs.len()
+                && form
+                    .kinds
+                    .iter()
+                    .zip(oprs)
+                    .all(|(kind, opr)| accepts(*kind, opr))
+        })
+        .ok_or_else(|| invalid_operands(op, pos))?;
+
+    let is_256 = vector_width(&form.kinds, oprs).ok_or_else(|| invalid_operands(op, pos))?;
+
+    let (reg, vvvv, rm) = match (form.order, oprs) {
+        (Order::Zo, []) => {
+            let mut bytes = vex_prefix(false, false, false, form.map, form.w, 0, is_256, form.pp);
+            bytes.push(form.opcode);
+            return Ok(bytes.into());
+        }
+        (Order::Rm, [reg, rm]) => (reg, None, rm),
+        (Order::Mr, [rm, reg]) => (reg, None, rm),
+        (Order::Rvm, [reg, vvvv, rm]) => (reg, Some(vvvv), rm),
+        _ => return Err(invalid_operands(op, pos)),
+    };
+
+    let vvvv = match vvvv {
+        Some(OperandNode::Register(reg)) => reg_code(reg).unwrap(),
+        _ => 0,
+    };
+    let reg = match reg {
+        OperandNode::Register(reg) => reg_code(reg).unwrap(),
+        _ => unreachable!(),
+    };
+    let modrm = encode_modrm(reg, rm, 1).ok_or_else(|| invalid_operands(op, pos))?;
+
+    let mut bytes = vex_prefix(
+        modrm.r, modrm.x, modrm.b, form.map, form.w, vvvv, is_256, form.pp,
+    );
+    bytes.push(form.opcode);
+    bytes.extend(modrm.bytes);
+    Ok(bytes.into())
+}
+
+// The 2-byte form is used whenever it can express the prefix, which requires
+// the 0F map, VEX.W0 and no extension of ModRM.rm or SIB.index.
+#[allow(clippy::too_many_arguments)]
+pub fn vex_prefix(
+    r: bool,
+    x: bool,
+    b: bool,
+    map: Map,
+    w: bool,
+    vvvv: u8,
+    is_256: bool,
+    pp: Pp,
+) -> Vec<u8> {
+    // R, X, B and vvvv are stored inverted
+    let last = (!vvvv & 0xf) << 3 | (is_256 as u8) << 2 | pp as u8;
+    if matches!(map, Map::M0f) && !w && !x && !b {
+        vec![0xc5, (!r as u8) << 7 | last]
+    } else {
+        let mmmmm = match map {
+            Map::M0f => 1,
+            Map::M0f38 => 2,
+            Map::M0f3a => 3,
+        };
+        vec![
+            0xc4,
+            (!r as u8) << 7 | (!x as u8) << 6 | (!b as u8) << 5 | mmmmm,
+            (w as u8) << 7 | last,
+        ]
+    }
+}
+
+fn forms(op: &Mnemonic) -> Vec<Form> {
+    use Map::*;
+    use Order::*;
+
+    let rvm = |pp, map, w, opcode| form(pp, map, w, opcode, Rvm, &[Vector, Vector, VectorOrMem]);
+    match op {
+        Mnemonic::Vmovdqu => vec![
+            form(Pp::F3, M0f, false, 0x6f, Rm, &[Vector, VectorOrMem]),
+            form(Pp::F3, M0f, false, 0x7f, Mr, &[Mem, Vector]),
+        ],
+        Mnemonic::Vmovdqa => vec![
+            form(Pp::P66, M0f, false, 0x6f, Rm, &[Vector, VectorOrMem]),
+            form(Pp::P66, M0f, false, 0x7f, Mr, &[Mem, Vector]),
+        ],
+        Mnemonic::Vmovups => vec![
+            form(Pp::None, M0f, false, 0x10, Rm, &[Vector, VectorOrMem]),
+            form(Pp::None, M0f, false, 0x11, Mr, &[Mem, Vector]),
+        ],
+        Mnemonic::Vmovaps => vec![
+            form(Pp::None, M0f, false, 0x28, Rm, &[Vector, VectorOrMem]),
+            form(Pp::None, M0f, false, 0x29, Mr, &[Mem, Vector]),
+        ],
+        Mnemonic::Vpaddd => vec![rvm(Pp::P66, M0f, false, 0xfe)],
+        Mnemonic::Vpsubd => vec![rvm(Pp::P66, M0f, false, 0xfa)],
+        Mnemonic::Vpand => vec![rvm(Pp::P66, M0f, false, 0xdb)],
+        Mnemonic::Vpor => vec![rvm(Pp::P66, M0f, false, 0xeb)],
+        Mnemonic::Vpxor => vec![rvm(Pp::P66, M0f, false, 0xef)],
+        Mnemonic::Vpcmpeqb => vec![rvm(Pp::P66, M0f, false, 0x74)],
+        Mnemonic::Vpcmpeqd => vec![rvm(Pp::P66, M0f, false, 0x76)],
+        Mnemonic::Vaddps => vec![rvm(Pp::None, M0f, false, 0x58)],
+        Mnemonic::Vaddpd => vec![rvm(Pp::P66, M0f, false, 0x58)],
+        Mnemonic::Vmulps => vec![rvm(Pp::None, M0f, false, 0x59)],
+        Mnemonic::Vmulpd => vec![rvm(Pp::P66, M0f, false, 0x59)],
+        Mnemonic::Vxorps => vec![rvm(Pp::None, M0f, false, 0x57)],
+        Mnemonic::Vfmadd231ps => vec![rvm(Pp::P66, M0f38, false, 0xb8)],
+        Mnemonic::Vfmadd231pd => vec![rvm(Pp::P66, M0f38, true, 0xb8)],
+        Mnemonic::Vpmovmskb => vec![
+            form(
+                Pp::P66,
+                M0f,
+                false,
+                0xd7,
+                Rm,
+                &[Gpr(OperandSize::Dword), Vector],
+            ),
+            form(
+                Pp::P66,
+                M0f,
+                false,
+                0xd7,
+                Rm,
+                &[Gpr(OperandSize::Qword), Vector],
+            ),
+        ],
+        Mnemonic::Vbroadcastss => vec![form(
+            Pp::P66,
+            M0f38,
+            false,
+            0x18,
+            Rm,
+            &[Vector, XmmOrMem(OperandSize::Dword)],
+        )],
+        Mnemonic::Vzeroupper => vec![form(Pp::None, M0f, false, 0x77, Zo, &[])],
+        _ => vec![],
+    }
+}
+
+fn accepts(kind: Kind, opr: &OperandNode) -> bool {
+    match (kind, opr) {
+        (Vector, OperandNode::Register(reg)) | (VectorOrMem, OperandNode::Register(reg)) => {
+            is_xmm(reg) || is_ymm(reg)
+        }
+        (XmmOrMem(_), OperandNode::Register(reg)) => is_xmm(reg),
+        (Gpr(size), OperandNode::Register(reg)) => register_size(reg) == Some(size),
+        (VectorOrMem, OperandNode::Memory(mem)) | (Mem, OperandNode::Memory(mem)) => {
+            matches!(
+                mem.size,
+                None | Some(OperandSize::Xmmword) | Some(OperandSize::Ymmword)
+            )
+        }
+        (XmmOrMem(size), OperandNode::Memory(mem)) => mem.size.is_none() || mem.size == Some(size),
+        _ => false,
+    }
+}
+
+// whether the vector operands are 256-bit, as long as they all have the same
+// width, where unsized memory takes the width of the registers
+fn vector_width(kinds: &[Kind], oprs: &[OperandNode]) -> Option<bool> {
+    let mut is_256 = None;
+    for (kind, opr) in kinds.iter().zip(oprs) {
+        if !matches!(kind, Vector | VectorOrMem | Mem) {
+            continue;
+        }
+        let width = match opr {
+            OperandNode::Register(reg) => Some(is_ymm(reg)),
+            OperandNode::Memory(mem) => mem.size.map(|size| size == OperandSize::Ymmword),
+            _ => None,
+        };
+        match (is_256, width) {
+            (None, _) => is_256 = width,
+            (Some(is_256), Some(width)) if is_256 != width => return None,
+            _ => {}
+        }
+    }
+    Some(is_256.unwrap_or(false))
+}
+
+pub fn is_ymm(reg: &Register) -> bool {
+    use Register::*;
+    matches!(
+        reg,
+        Ymm0 | Ymm1
+            | Ymm2
+            | Ymm3
+            | Ymm4
+            | Ymm5
+            | Ymm6
+            | Ymm7
+            | Ymm8
+            | Ymm9
+            | Ymm10
+            | Ymm11
+            | Ymm12
+            | Ymm13
+            | Ymm14
+            | Ymm15
+    )
+}
@@ -1,88 +1,253 @@
-use std::collections::HashMap;
-
-use x86asm::instruction::{
-    mnemonic::Mnemonic,
-    operand::{
-        immediate::Immediate,
-        memory::{Displacement, Memory},
-        offset::Offset,
-        Operand,
+use std::{collections::HashMap, convert::TryFrom};
+
+use x86asm::{
+    encode,
+    instruction::{
+        mnemonic::Mnemonic,
+        operand::{
+            immediate::Immediate,
+            memory::{Displacement, Memory, Scale, Size},
+            offset::Offset,
+            register::Register,
+            Operand,
+        },
+        Instruction,
     },
-    Instruction,
 };
 
 use crate::{
-    backend::gen_code::{Code, CodeItem, Codes, SectionName, SymbolType, UnresolvedSymbol},
-    frontend::parser::node::{DispNode, InstructionNode, OperandNode, Program, PseudoOp},
+    backend::gen_code::{
+        check_operand::{check_operands, operand_size, out_of_range, unexpected_operand_size},
+        encode_bit::{encode_bit, is_bit},
+        encode_evex::{encode_evex, is_evex},
+        encode_flow::{encode_flow, is_flow},
+        encode_prefix::encode_prefix,
+        encode_segment::{encode_segment, is_segment},
+        encode_sse::{encode_sse, is_sse},
+        encode_string::{encode_string, is_string},
+        encode_sync::{encode_sync, is_sync},
+        encode_system::{encode_system, is_system},
+        encode_vex::{encode_vex, is_vex},
+        mode::{adapt_prefixes, check_mode, has_memory, legacy_operand_size, widen, Mode},
+        modrm::Encoded,
+        relax_branch::{has_near_form, has_short_form, is_branch},
+        section_stack::{SectionStack, Subsection},
+        select_imm::select_imm,
+        Code, CodeItem, Codes, Options, SymbolType, UnresolvedSymbol,
+    },
+    common::{error::Error, pos::Pos},
+    frontend::{
+        lexer::token::Prefix,
+        parser::node::{
+            DispNode, InstructionNode, MemoryNode, OperandNode, OperandSize, Program, PseudoOp,
+        },
+    },
 };
 
+type Encoder = fn(&Mnemonic, &[OperandNode], Mode, &Pos) -> Result<Encoded, Error>;
+
+// the instructions x86asm cannot encode, tried in order
+const ENCODERS: &[(fn(&Mnemonic, &[OperandNode]) -> bool, Encoder)] = &[
+    (is_string, |op, _, _, _| Ok(encode_string(op).into())),
+    (is_segment, encode_segment),
+    (is_system, |op, oprs, _, pos| encode_system(op, oprs, pos)),
+    (
+        |op, _| is_bit(op),
+        |op, oprs, _, pos| encode_bit(op, oprs, pos),
+    ),
+    (is_flow, |op, oprs, _, pos| encode_flow(op, oprs, pos)),
+    (is_sync, |op, oprs, _, pos| encode_sync(op, oprs, pos)),
+    (is_evex, |op, oprs, _, pos| encode_evex(op, oprs, pos)),
+    (
+        |op, _| is_sse(op),
+        |op, oprs, _, pos| encode_sse(op, oprs, pos),
+    ),
+    (
+        |op, _| is_vex(op),
+        |op, oprs, _, pos| encode_vex(op, oprs, pos),
+    ),
+];
+
 pub struct CodeGen {
-    codes: Codes,
-    current_section: SectionName,
+    codes: HashMap<Subsection, Code>,
+    sections: SectionStack,
+    options: Options,
+    mode: Mode,
 }
 
 impl CodeGen {
-    pub fn new() -> Self {
+    pub fn new(options: Options) -> Self {
         Self {
             codes: HashMap::new(),
-            current_section: SectionName::Text,
+            sections: SectionStack::new(),
+            options,
+            mode: Mode::Bits64,
         }
     }
 
-    pub fn gen_program(mut self, program: Program) -> Codes {
-        for inst in program.insts {
-            self.gen_inst(inst);
+    pub fn gen_program(mut self, program: Program) -> Result<Codes, Error> {
+        for stmt in program.stmts {
+            self.gen_inst(stmt.node, &stmt.pos)?;
         }
 
-        self.codes
+        Ok(merge_subsections(self.codes))
     }
 
-    fn gen_inst(&mut self, inst: InstructionNode) {
+    fn gen_inst(&mut self, inst: InstructionNode, pos: &Pos) -> Result<(), Error> {
         match inst {
-            InstructionNode::NullaryOp(op) => {
-                self.add_item(CodeItem::Inst(Instruction::new_nullary(op)));
-            }
-            InstructionNode::UnaryOp(op, opr1) => {
-                let opr1 = self.opr2opr(opr1);
-                self.add_item(CodeItem::Inst(Instruction::new_unary(op, opr1)));
-            }
-            InstructionNode::BinaryOp(op, opr1, opr2) => {
-                if matches!(op, Mnemonic::Mov) {
-                    self.gen_mov(opr1, opr2);
-                    return;
-                }
-
-                let opr1 = self.opr2opr(opr1);
-                let opr2 = self.opr2opr(opr2);
-                self.add_item(CodeItem::Inst(Instruction::new_binary(op, opr1, opr2)));
-            }
+            InstructionNode::Op(prefix, op, oprs) => self.gen_op(prefix, op, oprs, pos)?,
             InstructionNode::PseudoOp(op, args) => match op {
-                PseudoOp::Data => self.current_section = SectionName::Data,
-                PseudoOp::Text => self.current_section = SectionName::Text,
                 PseudoOp::Zero => self.gen_zero(*args[0].as_integer()),
                 PseudoOp::Ascii => self.gen_ascii(args[0].as_string()),
-                _ => {}
+                PseudoOp::Code16 => self.mode = Mode::Bits16,
+                PseudoOp::Code32 => self.mode = Mode::Bits32,
+                PseudoOp::Code64 => self.mode = Mode::Bits64,
+                _ => self.sections.switch(&op, &args, pos)?,
             },
             _ => {}
         }
+        Ok(())
+    }
+
+    fn gen_op(
+        &mut self,
+        prefix: Option<Prefix>,
+        op: Mnemonic,
+        oprs: Vec<OperandNode>,
+        pos: &Pos,
+    ) -> Result<(), Error> {
+        check_mode(&op, &oprs, self.mode, pos)?;
+        let legacy_size = legacy_operand_size(&op, &oprs, self.mode);
+        let has_memory = has_memory(&oprs);
+        let oprs = widen(&op, oprs, self.mode);
+        check_operands(&op, &oprs, pos)?;
+
+        // every statement is a single item, which label addresses count on, so a
+        // prefix goes in the same item as the instruction it applies to
+        let prefix = match prefix {
+            Some(prefix) => Some(encode_prefix(prefix, &op, &oprs, pos)?),
+            None => None,
+        };
+
+        if let Some((_, encode)) = ENCODERS.iter().find(|(is_form, _)| is_form(&op, &oprs)) {
+            let mut encoded = encode(&op, &oprs, self.mode, pos)?;
+            encoded.bytes = self.adapt(encoded.bytes, legacy_size, has_memory);
+            self.gen_encoded(prefix, encoded, &oprs, pos);
+            return Ok(());
+        }
+
+        // movsx with a 32-bit source is movsxd
+        let op = match op {
+            Mnemonic::Movsx if oprs.get(1).and_then(operand_size) == Some(OperandSize::Dword) => {
+                Mnemonic::Movsxd
+            }
+            _ => op,
+        };
+
+        let oprs = match op {
+            Mnemonic::Mov => narrow_mov(oprs),
+            _ => oprs,
+        };
+
+        // immediates are pushed as words in 16-bit mode
+        let size = match op {
+            Mnemonic::Push => legacy_size,
+            _ => oprs.first().and_then(operand_size),
+        };
+        let is_reg_dest = matches!(oprs.first(), Some(OperandNode::Register(_)));
+
+        let mut memory = None;
+        let mut converted = Vec::with_capacity(oprs.len());
+        for (i, opr) in oprs.into_iter().enumerate() {
+            match opr {
+                OperandNode::Memory(mem) => memory = Some((i, mem)),
+                opr => converted.push(self.opr2opr(&op, size, is_reg_dest, opr, pos)?),
+            }
+        }
+        // a displacement is followed by the immediate, if any, whose size is only
+        // known once converted
+        if let Some((i, mem)) = memory {
+            let trailing = converted.iter().map(immediate_size).sum();
+            converted.insert(i, Operand::Memory(self.mem2mem(mem, trailing, pos)?));
+        }
+        let mut oprs = converted;
+
+        let is_branch = matches!(oprs.as_slice(), [Operand::Offset(_)]);
+        if let [Operand::Offset(offset)] = oprs.as_mut_slice() {
+            *offset = self.branch_offset(&op);
+        }
+
+        let inst = new_inst(op, oprs);
+        let item = match (self.mode, prefix) {
+            (Mode::Bits64, None) => CodeItem::Inst(inst),
+            // relative branches encode the same in 32-bit mode, and only their
+            // rel32 is narrowed in 16-bit mode, so they stay relaxable
+            (Mode::Bits32, None) if is_branch => CodeItem::Inst(inst),
+            (Mode::Bits16, None) if is_branch => CodeItem::Branch16(inst),
+            _ => {
+                let mut bytes: Vec<u8> = prefix.into_iter().collect();
+                let encoded = encode::encode(&inst);
+                bytes.extend(self.adapt(encoded, legacy_size, has_memory));
+                CodeItem::Raw(bytes)
+            }
+        };
+        self.add_item(item);
+        Ok(())
     }
 
-    fn gen_mov(&mut self, opr1: OperandNode, opr2: OperandNode) {
-        let opr1 = self.opr2opr(opr1);
-        let mut opr2 = self.opr2opr(opr2);
+    fn adapt(&self, bytes: Vec<u8>, size: Option<OperandSize>, has_memory: bool) -> Vec<u8> {
+        adapt_prefixes(bytes, size, has_memory, self.mode)
+    }
 
-        if let Operand::Immediate(Immediate::Imm8(value)) = opr2 {
-            opr2 = Operand::Immediate(Immediate::Imm32(value as i32));
+    // adds an instruction encoded by one of the encode_* modules, for the
+    // forms that x86asm does not have
+    fn gen_encoded(
+        &mut self,
+        prefix: Option<u8>,
+        encoded: Encoded,
+        oprs: &[OperandNode],
+        pos: &Pos,
+    ) {
+        for opr in oprs {
+            match opr.unmasked() {
+                OperandNode::Memory(MemoryNode {
+                    base,
+                    disp: Some(DispNode::Label { name, addend }),
+                    ..
+                }) => {
+                    let typ = disp_symbol_type(base, self.mode);
+                    self.add_unresolved_symbol(name.clone(), typ, *addend, encoded.trailing, pos);
+                }
+                OperandNode::FarPointer {
+                    offset: DispNode::Label { name, addend },
+                    ..
+                } => {
+                    // the offset is followed by the 16-bit segment
+                    let typ = match self.mode {
+                        Mode::Bits16 => SymbolType::Abs16,
+                        _ => SymbolType::Abs32,
+                    };
+                    self.add_unresolved_symbol(name.clone(), typ, *addend, 2, pos);
+                }
+                _ => {}
+            }
         }
+        let mut prefixed: Vec<u8> = prefix.into_iter().collect();
+        prefixed.extend(encoded.bytes);
+        self.add_item(CodeItem::Raw(prefixed));
+    }
 
-        self.add_item(CodeItem::Inst(Instruction::new_binary(
-            Mnemonic::Mov,
-            opr1,
-            opr2,
-        )));
+    // branches with a rel8 form start out short and are grown by relax_branches
+    fn branch_offset(&self, op: &Mnemonic) -> Offset {
+        let is_long = self.options.force_long_branches && has_near_form(op);
+        if has_short_form(op) && !is_long {
+            Offset::Off8(0)
+        } else {
+            Offset::Off32(0)
+        }
     }
 
-    fn gen_zero(&mut self, arg: i32) {
+    fn gen_zero(&mut self, arg: i64) {
         if arg.is_negative() {
             return;
         }
@@ -96,51 +261,95 @@ impl CodeGen {
         self.add_item(CodeItem::Raw(arg_content.as_bytes().to_vec()));
     }
 
-    fn opr2opr(&mut self, opr: OperandNode) -> Operand {
-        match opr {
+    // `size` and `is_reg_dest` describe the destination, which selects the immediate form
+    fn opr2opr(
+        &mut self,
+        op: &Mnemonic,
+        size: Option<OperandSize>,
+        is_reg_dest: bool,
+        opr: OperandNode,
+        pos: &Pos,
+    ) -> Result<Operand, Error> {
+        let opr = match opr {
             OperandNode::Immidiate(value) => {
-                if value < 0x80 {
-                    Operand::Immediate(Immediate::Imm8(value as i8))
+                Operand::Immediate(select_imm(op, size, is_reg_dest, value, pos)?)
+            }
+            OperandNode::Register(reg) => Operand::Register(reg),
+            OperandNode::Label { name, addend } => {
+                let typ = label_type(op, size, is_reg_dest, self.mode, pos)?;
+                self.add_unresolved_symbol(name, typ, addend, 0, pos);
+                match typ {
+                    SymbolType::Jump => Operand::Offset(Offset::Off32(0)),
+                    SymbolType::Abs16 => Operand::Immediate(Immediate::Imm16(0)),
+                    SymbolType::Abs64 => Operand::Immediate(Immediate::Imm64(0)),
+                    _ => Operand::Immediate(Immediate::Imm32(0)),
+                }
+            }
+            // check_operands only lets EVEX instructions have decorations, far
+            // pointers are encoded by encode_segment, and memory is converted
+            // by mem2mem
+            OperandNode::Masked { .. }
+            | OperandNode::Rounding(_)
+            | OperandNode::FarPointer { .. }
+            | OperandNode::Memory(_) => unreachable!(),
+        };
+        Ok(opr)
+    }
+
+    // `trailing` is the number of bytes after the displacement
+    fn mem2mem(&mut self, mem: MemoryNode, trailing: u32, pos: &Pos) -> Result<Memory, Error> {
+        // an address without a base always has a 32-bit displacement
+        let has_base = mem.base.is_some();
+        let disp = match mem.disp {
+            None if has_base => None,
+            None => Some(Displacement::Disp32(0)),
+            Some(DispNode::Immediate(disp)) => {
+                if let (Ok(disp), true) = (i8::try_from(disp), has_base) {
+                    Some(Displacement::Disp8(disp))
+                } else if let Ok(disp) = i32::try_from(disp) {
+                    Some(Displacement::Disp32(disp))
                 } else {
-                    Operand::Immediate(Immediate::Imm32(value as i32))
+                    return Err(out_of_range(disp, pos));
                 }
             }
-            OperandNode::Register(reg) => Operand::Register(reg),
-            OperandNode::Memory(mem) => Operand::Memory(Memory::new(
-                mem.base,
-                mem.disp.map(|disp| match disp {
-                    DispNode::Immediate(disp) => {
-                        if disp >= -0x80 && disp < 0x80 {
-                            Displacement::Disp8(disp as i8)
-                        } else {
-                            Displacement::Disp32(disp as i32)
-                        }
-                    }
-                    DispNode::Label(symbol_name) => {
-                        let cur_section = self.cur_section();
-                        let item_index = cur_section.items.len();
-                        cur_section.unresolved_symbols.push(UnresolvedSymbol {
-                            symbol_name,
-                            typ: SymbolType::Addr,
-                            item_index,
-                        });
-
-                        Displacement::Disp32(0)
-                    }
-                }),
-            )),
-            OperandNode::Label(symbol_name) => {
-                let cur_section = self.cur_section();
-                let item_index = cur_section.items.len();
-                cur_section.unresolved_symbols.push(UnresolvedSymbol {
-                    symbol_name,
-                    typ: SymbolType::Jump,
-                    item_index,
-                });
-
-                Operand::Offset(Offset::Off32(0))
+            Some(DispNode::Label { name, addend }) => {
+                let typ = disp_symbol_type(&mem.base, self.mode);
+                self.add_unresolved_symbol(name, typ, addend, trailing, pos);
+                Some(Displacement::Disp32(0))
             }
-        }
+        };
+
+        let mut memory = match (mem.base, mem.index) {
+            (Some(base), None) => Memory::new(base, disp),
+            (base, Some(index)) => Memory::new_sib(base, index.reg, scale2scale(index.scale), disp),
+            // an absolute address needs a SIB byte without a base or an index,
+            // where rsp as the index means none
+            (None, None) => Memory::new_sib(None, Register::Rsp, Scale::Scale1, disp),
+        };
+        memory.size = mem.size.map(size2size);
+        Ok(memory)
+    }
+
+    // the symbol is referenced by the item that is added next, where `trailing`
+    // bytes follow the relocated field
+    fn add_unresolved_symbol(
+        &mut self,
+        symbol_name: String,
+        typ: SymbolType,
+        addend: i64,
+        trailing: u32,
+        pos: &Pos,
+    ) {
+        let cur_section = self.cur_section();
+        let item_index = cur_section.items.len();
+        cur_section.unresolved_symbols.push(UnresolvedSymbol {
+            symbol_name,
+            typ,
+            item_index,
+            addend,
+            trailing,
+            pos: pos.clone(),
+        });
     }
 
     fn add_item(&mut self, item: CodeItem) {
@@ -149,7 +358,154 @@ impl CodeGen {
 
     fn cur_section(&mut self) -> &mut Code {
         self.codes
-            .entry(self.current_section.clone())
+            .entry(self.sections.current().clone())
             .or_insert_with(Code::default)
     }
 }
+
+// only rip-relative displacements are relative to the instruction, and only
+// 64-bit addresses sign-extend them
+fn disp_symbol_type(base: &Option<Register>, mode: Mode) -> SymbolType {
+    match base {
+        Some(Register::Rip) => SymbolType::Addr,
+        _ if mode == Mode::Bits64 => SymbolType::Abs32S,
+        _ => SymbolType::Abs32,
+    }
+}
+
+// a label is a branch target, or an absolute address used as an immediate
+fn label_type(
+    op: &Mnemonic,
+    size: Option<OperandSize>,
+    is_reg_dest: bool,
+    mode: Mode,
+    pos: &Pos,
+) -> Result<SymbolType, Error> {
+    use OperandSize::*;
+    let typ = if is_branch(op) {
+        SymbolType::Jump
+    } else if mode != Mode::Bits64 {
+        match size {
+            Some(Word) => SymbolType::Abs16,
+            // there is nothing to sign-extend to
+            Some(Dword) | None => SymbolType::Abs32,
+            Some(size) => return Err(unexpected_operand_size(size, pos)),
+        }
+    } else {
+        match size {
+            // `mov r64, symbol` loads the full 64-bit address
+            Some(Qword) if matches!(op, Mnemonic::Mov) && is_reg_dest => SymbolType::Abs64,
+            Some(Dword) => SymbolType::Abs32,
+            // 64-bit operations sign-extend their imm32
+            Some(Qword) | None => SymbolType::Abs32S,
+            Some(size) => return Err(unexpected_operand_size(size, pos)),
+        }
+    };
+    Ok(typ)
+}
+
+// `mov r64, imm32` sign-extends, so values that only fit as unsigned 32-bit
+// integers are moved through the 32-bit register, which zero-extends
+fn narrow_mov(mut oprs: Vec<OperandNode>) -> Vec<OperandNode> {
+    if let [OperandNode::Register(reg), OperandNode::Immidiate(value)] = oprs.as_mut_slice() {
+        if i32::try_from(*value).is_err() && u32::try_from(*value).is_ok() {
+            if let Some(reg32) = reg32(reg) {
+                *reg = reg32;
+            }
+        }
+    }
+    oprs
+}
+
+fn reg32(reg: &Register) -> Option<Register> {
+    use Register::*;
+    let reg32 = match reg {
+        Rax => Eax,
+        Rcx => Ecx,
+        Rdx => Edx,
+        Rbx => Ebx,
+        Rsp => Esp,
+        Rbp => Ebp,
+        Rsi => Esi,
+        Rdi => Edi,
+        R8 => R8d,
+        R9 => R9d,
+        R10 => R10d,
+        R11 => R11d,
+        R12 => R12d,
+        R13 => R13d,
+        R14 => R14d,
+        R15 => R15d,
+        _ => return None,
+    };
+    Some(reg32)
+}
+
+// concatenates the subsections of each section in ascending order of their numbers
+fn merge_subsections(codes: HashMap<Subsection, Code>) -> Codes {
+    let mut subsections: Vec<(Subsection, Code)> = codes.into_iter().collect();
+    subsections.sort_by_key(|(subsection, _)| subsection.number);
+
+    let mut merged = Codes::new();
+    for (subsection, code) in subsections {
+        let section = merged.entry(subsection.name).or_insert_with(Code::default);
+
+        let base_index = section.items.len();
+        section.items.extend(code.items);
+        section
+            .unresolved_symbols
+            .extend(code.unresolved_symbols.into_iter().map(|mut symbol| {
+                symbol.item_index += base_index;
+                symbol
+            }));
+    }
+    merged
+}
+
+fn new_inst(op: Mnemonic, oprs: Vec<Operand>) -> Instruction {
+    let mut oprs = oprs.into_iter();
+    match (oprs.next(), oprs.next(), oprs.next(), oprs.next()) {
+        (None, _, _, _) => Instruction::new_nullary(op),
+        (Some(opr1), None, _, _) => Instruction::new_unary(op, opr1),
+        (Some(opr1), Some(opr2), None, _) => Instruction::new_binary(op, opr1, opr2),
+        (Some(opr1), Some(opr2), Some(opr3), None) => {
+            Instruction::new_ternary(op, opr1, opr2, opr3)
+        }
+        (Some(opr1), Some(opr2), Some(opr3), Some(opr4)) => {
+            Instruction::new_quaternary(op, opr1, opr2, opr3, opr4)
+        }
+    }
+}
+
+fn immediate_size(opr: &Operand) -> u32 {
+    match opr {
+        Operand::Immediate(Immediate::Imm8(_)) => 1,
+        Operand::Immediate(Immediate::Imm16(_)) => 2,
+        Operand::Immediate(Immediate::Imm32(_)) => 4,
+        Operand::Immediate(Immediate::Imm64(_)) => 8,
+        _ => 0,
+    }
+}
+
+fn scale2scale(scale: u8) -> Scale {
+    match scale {
+        1 => Scale::Scale1,
+        2 => Scale::Scale2,
+        4 => Scale::Scale4,
+        8 => Scale::Scale8,
+        _ => unreachable!(),
+    }
+}
+
+fn size2size(size: OperandSize) -> Size {
+    match size {
+        OperandSize::Byte => Size::Byte,
+        OperandSize::Word => Size::Word,
+        OperandSize::Dword => Size::Dword,
+        OperandSize::Qword => Size::Qword,
+        // check_operands only allows far pointers in memory for far jumps and calls
+        OperandSize::Fword => unreachable!(),
+        // check_operands only allows vector memory for vector instructions
+        OperandSize::Xmmword | OperandSize::Ymmword | OperandSize::Zmmword => unreachable!(),
+    }
+}
@@ -0,0 +1,295 @@
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::{
+        check_operand::{
+            is_extended, operand_size, register_size, unexpected_operand_size, unexpected_register,
+        },
+        encode_bit::is_bmi,
+        encode_evex::has_evex_form,
+        encode_segment::is_segment_register,
+        encode_sse::is_sse,
+        encode_string::string_size,
+        encode_vex::is_vex,
+        modrm::reg_code,
+    },
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
+    frontend::parser::node::{MemoryNode, OperandNode, OperandSize},
+};
+
+// the default operand and address size, selected by `.code16`, `.code32` and `.code64`
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Mode {
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl Mode {
+    pub fn bits(self) -> u8 {
+        match self {
+            Mode::Bits16 => 16,
+            Mode::Bits32 => 32,
+            Mode::Bits64 => 64,
+        }
+    }
+}
+
+// rejects instructions and operands that only exist in 64-bit mode, and
+// addresses that the mode cannot encode
+pub fn check_mode(op: &Mnemonic, oprs: &[OperandNode], mode: Mode, pos: &Pos) -> Result<(), Error> {
+    for opr in oprs {
+        if let OperandNode::Memory(mem) = opr.unmasked() {
+            check_address(mem, mode, pos)?;
+        }
+    }
+    if mode == Mode::Bits64 {
+        return Ok(());
+    }
+
+    if is_long_mode_only(op) {
+        return Err(Error::new(
+            pos.clone(),
+            ErrorKind::InvalidInMode {
+                mnemonic: op.clone(),
+                bits: mode.bits(),
+            },
+        ));
+    }
+
+    for opr in oprs {
+        match opr.unmasked() {
+            OperandNode::Register(reg) if is_long_mode_register(reg) => {
+                return Err(unexpected_register(reg, pos));
+            }
+            // vector instructions have 64-bit memory operands in every mode
+            OperandNode::Memory(mem) if mem.size == Some(OperandSize::Qword) && !is_vector(op) => {
+                return Err(unexpected_operand_size(OperandSize::Qword, pos));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// The encoders only know 64-bit mode, where a 32-bit address register encodes
+// the same as its 64-bit counterpart but needs 0x67. Likewise, pushes, pops,
+// indirect branches and moves to or from control and debug registers take the
+// 32-bit register of narrower modes where 64-bit mode has the 64-bit one.
+pub fn widen(op: &Mnemonic, oprs: Vec<OperandNode>, mode: Mode) -> Vec<OperandNode> {
+    use OperandSize::*;
+    if mode == Mode::Bits64 {
+        return oprs;
+    }
+
+    let is_stack = matches!(op, Mnemonic::Push | Mnemonic::Pop);
+    let is_branch = matches!(op, Mnemonic::Call | Mnemonic::Jmp);
+    let is_special_mov = *op == Mnemonic::Mov
+        && oprs.iter().any(|opr| match opr {
+            OperandNode::Register(reg) => is_control_or_debug(reg),
+            _ => false,
+        });
+
+    oprs.into_iter()
+        .map(|opr| match opr {
+            OperandNode::Register(reg) => {
+                let is_wide = match register_size(&reg) {
+                    Some(Dword) => is_stack || is_branch || is_special_mov,
+                    Some(Word) => is_branch,
+                    _ => false,
+                };
+                match reg64(&reg) {
+                    Some(reg64) if is_wide => OperandNode::Register(reg64),
+                    _ => OperandNode::Register(reg),
+                }
+            }
+            OperandNode::Memory(mut mem) => {
+                mem.base = mem.base.map(|reg| reg64(&reg).unwrap_or(reg));
+                if let Some(index) = mem.index.as_mut() {
+                    index.reg = reg64(&index.reg).unwrap_or_else(|| index.reg.clone());
+                }
+                let is_wide = match mem.size {
+                    Some(Dword) => is_stack || is_branch,
+                    Some(Word) => is_branch,
+                    _ => false,
+                };
+                if is_wide {
+                    mem.size = Some(Qword);
+                }
+                OperandNode::Memory(mem)
+            }
+            opr => opr,
+        })
+        .collect()
+}
+
+// the operand size that 0x66 selects between, if the instruction has one
+pub fn legacy_operand_size(op: &Mnemonic, oprs: &[OperandNode], mode: Mode) -> Option<OperandSize> {
+    use Mnemonic::*;
+    // the mandatory prefixes of vector instructions are left alone, and BMI
+    // instructions are VEX-encoded
+    if is_vector(op) || is_bmi(op) {
+        return None;
+    }
+    if oprs.is_empty() {
+        return match op {
+            Cbw | Cwd => Some(OperandSize::Word),
+            Cwde | Cdq => Some(OperandSize::Dword),
+            op => string_size(op),
+        };
+    }
+
+    match (op, oprs) {
+        // only a store of a segment register to a register has an operand size
+        (Mov, [OperandNode::Register(dest), OperandNode::Register(src)])
+            if is_segment_register(src) =>
+        {
+            register_size(dest)
+        }
+        (Mov, _)
+            if oprs.iter().any(|opr| match opr {
+                OperandNode::Register(reg) => register_size(reg).is_none(),
+                _ => false,
+            }) =>
+        {
+            None
+        }
+        (Out, [_, src]) => operand_size(src),
+        // immediates are pushed as words in 16-bit mode
+        (Push, [OperandNode::Immidiate(_)]) | (Push, [OperandNode::Label { .. }])
+            if mode == Mode::Bits16 =>
+        {
+            Some(OperandSize::Word)
+        }
+        _ => oprs.iter().find_map(operand_size),
+    }
+}
+
+// Adapts the prefixes of an instruction encoded for 64-bit mode, where 0x66
+// selects a 16-bit operand. 16-bit mode swaps the meaning of the prefix and
+// needs 0x67 for the 32-bit addresses that are the default elsewhere.
+pub fn adapt_prefixes(
+    mut bytes: Vec<u8>,
+    size: Option<OperandSize>,
+    has_memory: bool,
+    mode: Mode,
+) -> Vec<u8> {
+    use OperandSize::*;
+    if mode == Mode::Bits64 {
+        return bytes;
+    }
+
+    let has_prefix = bytes.first() == Some(&0x66);
+    let needs_prefix = match (mode, size) {
+        (Mode::Bits16, Some(Dword)) | (Mode::Bits16, Some(Fword)) => true,
+        (Mode::Bits32, Some(Word)) => true,
+        (_, Some(Word)) | (_, Some(Dword)) | (_, Some(Fword)) => false,
+        _ => has_prefix,
+    };
+    if has_prefix && !needs_prefix {
+        bytes.remove(0);
+    } else if !has_prefix && needs_prefix {
+        bytes.insert(0, 0x66);
+    }
+
+    if mode == Mode::Bits16 && has_memory {
+        bytes.insert(0, 0x67);
+    }
+    bytes
+}
+
+fn is_vector(op: &Mnemonic) -> bool {
+    is_sse(op) || is_vex(op) || has_evex_form(op)
+}
+
+pub fn has_memory(oprs: &[OperandNode]) -> bool {
+    oprs.iter()
+        .any(|opr| matches!(opr.unmasked(), OperandNode::Memory(_)))
+}
+
+// instructions that need REX.W, or whose opcode means something else elsewhere
+fn is_long_mode_only(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        op,
+        Cdqe | Cqo
+            | Movsxd
+            | Iretq
+            | Sysretq
+            | Swapgs
+            | Jrcxz
+            | Movsq
+            | Cmpsq
+            | Stosq
+            | Lodsq
+            | Scasq
+    )
+}
+
+// registers that need REX, EVEX or a 64-bit operand size
+fn is_long_mode_register(reg: &Register) -> bool {
+    use Register::*;
+    register_size(reg) == Some(OperandSize::Qword)
+        || matches!(reg, Spl | Bpl | Sil | Dil | Rip)
+        || reg_code(reg).map_or(false, |code| code >= 8)
+}
+
+fn is_control_or_debug(reg: &Register) -> bool {
+    register_size(reg).is_none() && !is_segment_register(reg)
+}
+
+// Addresses are made of the general-purpose registers of the address size,
+// which is 64-bit in 64-bit mode and 32-bit otherwise. Only 64-bit mode has rip
+// as a base, and the stack pointer cannot be an index. The 16-bit forms like
+// `[bx+si]` are not implemented, even in 16-bit mode.
+fn check_address(mem: &MemoryNode, mode: Mode, pos: &Pos) -> Result<(), Error> {
+    for reg in address_registers(mem) {
+        if register_size(reg) == Some(OperandSize::Word) {
+            return Err(Error::new(
+                pos.clone(),
+                ErrorKind::UnsupportedAddressSize { bits: 16 },
+            ));
+        }
+    }
+
+    let is_address = |reg: &Register| match mode {
+        Mode::Bits64 => register_size(reg) == Some(OperandSize::Qword),
+        _ => register_size(reg) == Some(OperandSize::Dword) && !is_extended(reg),
+    };
+    if let Some(base) = &mem.base {
+        if !is_address(base) && !(mode == Mode::Bits64 && matches!(base, Register::Rip)) {
+            return Err(unexpected_register(base, pos));
+        }
+    }
+    if let Some(index) = &mem.index {
+        if !is_address(&index.reg) || matches!(index.reg, Register::Rsp | Register::Esp) {
+            return Err(unexpected_register(&index.reg, pos));
+        }
+    }
+    Ok(())
+}
+
+fn address_registers(mem: &MemoryNode) -> impl Iterator<Item = &Register> {
+    mem.base
+        .iter()
+        .chain(mem.index.as_ref().map(|index| &index.reg))
+}
+
+fn reg64(reg: &Register) -> Option<Register> {
+    use Register::*;
+    let reg64 = match reg {
+        Eax | Ax => Rax,
+        Ecx | Cx => Rcx,
+        Edx | Dx => Rdx,
+        Ebx | Bx => Rbx,
+        Esp | Sp => Rsp,
+        Ebp | Bp => Rbp,
+        Esi | Si => Rsi,
+        Edi | Di => Rdi,
+        _ => return None,
+    };
+    Some(reg64)
+}
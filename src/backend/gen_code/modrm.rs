@@ -0,0 +1,251 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
+
+use crate::{
+    backend::gen_code::check_operand::{
+        invalid_operands, unexpected_operand_size, unexpected_register,
+    },
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::{DispNode, MemoryNode, OperandNode, OperandSize},
+};
+
+// The ModRM byte, with the SIB byte and displacement that follow it. Register
+// numbers above 7 do not fit in these bytes, so their high bits are kept for
+// the REX or VEX prefix.
+pub struct ModRm {
+    pub bytes: Vec<u8>,
+    // extends ModRM.reg
+    pub r: bool,
+    // extends SIB.index
+    pub x: bool,
+    // extends ModRM.rm or SIB.base
+    pub b: bool,
+}
+
+// An instruction encoded by one of the encode_* modules. A displacement is
+// followed by the immediate, if any, so the immediate's size is kept to find
+// where the displacement is and, for a rip-relative one, what it is relative to.
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    pub trailing: u32,
+}
+
+impl Encoded {
+    pub fn push_immediate(&mut self, imm: &[u8]) {
+        self.bytes.extend_from_slice(imm);
+        self.trailing += imm.len() as u32;
+    }
+}
+
+impl From<Vec<u8>> for Encoded {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self { bytes, trailing: 0 }
+    }
+}
+
+// `reg` is the register number or opcode extension put in ModRM.reg.
+// An 8-bit displacement is stored divided by `disp_scale`, which is only
+// above 1 for EVEX. Label displacements are left as zero to be relocated.
+pub fn encode_modrm(reg: u8, rm: &OperandNode, disp_scale: i32) -> Option<ModRm> {
+    match rm {
+        OperandNode::Register(rm) => {
+            let rm = reg_code(rm)?;
+            Some(ModRm {
+                bytes: vec![modrm(0b11, reg, rm)],
+                r: reg & 8 != 0,
+                x: false,
+                b: rm & 8 != 0,
+            })
+        }
+        OperandNode::Memory(mem) => encode_memory(reg, mem, disp_scale),
+        _ => None,
+    }
+}
+
+fn encode_memory(reg: u8, mem: &MemoryNode, disp_scale: i32) -> Option<ModRm> {
+    let disp = match mem.disp {
+        None => None,
+        Some(DispNode::Immediate(disp)) => Some(i32::try_from(disp).ok()?),
+        Some(DispNode::Label { .. }) => Some(0),
+    };
+    let is_label = matches!(mem.disp, Some(DispNode::Label { .. }));
+
+    let mut modrm_bytes = Vec::new();
+    let mut x = false;
+    let mut b = false;
+
+    match (&mem.base, &mem.index) {
+        // rip-relative addresses are always followed by a 32-bit displacement
+        (Some(Register::Rip), None) => {
+            modrm_bytes.push(modrm(0b00, reg, 0b101));
+            modrm_bytes.extend_from_slice(&disp.unwrap_or(0).to_le_bytes());
+        }
+        // an address without a base always has a 32-bit displacement
+        (None, Some(index)) => {
+            let index_code = reg_code(&index.reg)?;
+            x = index_code & 8 != 0;
+            modrm_bytes.push(modrm(0b00, reg, 0b100));
+            modrm_bytes.push(sib(index.scale, index_code, 0b101));
+            modrm_bytes.extend_from_slice(&disp.unwrap_or(0).to_le_bytes());
+        }
+        (Some(base), index) => {
+            let base_code = reg_code(base)?;
+            b = base_code & 8 != 0;
+
+            // rbp and r13 cannot be used as a base without a displacement
+            let disp = match disp {
+                None if base_code & 7 == 0b101 => Some(0),
+                disp => disp,
+            };
+            let (mod_bits, disp_bytes) = match disp {
+                None => (0b00, vec![]),
+                Some(disp) if !is_label && disp % disp_scale == 0 => {
+                    match i8::try_from(disp / disp_scale) {
+                        Ok(disp8) => (0b01, vec![disp8 as u8]),
+                        Err(_) => (0b10, disp.to_le_bytes().to_vec()),
+                    }
+                }
+                Some(disp) => (0b10, disp.to_le_bytes().to_vec()),
+            };
+
+            match index {
+                Some(index) => {
+                    let index_code = reg_code(&index.reg)?;
+                    x = index_code & 8 != 0;
+                    modrm_bytes.push(modrm(mod_bits, reg, 0b100));
+                    modrm_bytes.push(sib(index.scale, index_code, base_code));
+                }
+                // rsp and r12 as a base need a SIB byte without an index
+                None if base_code & 7 == 0b100 => {
+                    modrm_bytes.push(modrm(mod_bits, reg, 0b100));
+                    modrm_bytes.push(sib(1, 0b100, base_code));
+                }
+                None => modrm_bytes.push(modrm(mod_bits, reg, base_code)),
+            }
+            modrm_bytes.extend(disp_bytes);
+        }
+        // an absolute address, where 0b100 as the index means none
+        (None, None) => {
+            modrm_bytes.push(modrm(0b00, reg, 0b100));
+            modrm_bytes.push(sib(1, 0b100, 0b101));
+            modrm_bytes.extend_from_slice(&disp.unwrap_or(0).to_le_bytes());
+        }
+    }
+
+    Some(ModRm {
+        bytes: modrm_bytes,
+        r: reg & 8 != 0,
+        x,
+        b,
+    })
+}
+
+// `opcode /reg` without a VEX or EVEX prefix, which needs REX.W for a 64-bit
+// operand size or to extend ModRM
+pub fn encode_legacy(opcode: &[u8], reg: u8, is_wide: bool, rm: &OperandNode) -> Option<Vec<u8>> {
+    let modrm = encode_modrm(reg, rm, 1)?;
+    let mut bytes = Vec::new();
+    if is_wide || modrm.r || modrm.x || modrm.b {
+        bytes.push(rex(is_wide, modrm.r, modrm.x, modrm.b));
+    }
+    bytes.extend_from_slice(opcode);
+    bytes.extend(modrm.bytes);
+    Some(bytes)
+}
+
+// `opcode /reg` with a 16-, 32- or 64-bit operand size, where 0x66 comes
+// before any mandatory prefix
+pub fn encode_sized(
+    prefix: Option<u8>,
+    opcode: &[u8],
+    reg: u8,
+    rm: &OperandNode,
+    size: OperandSize,
+) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if size == OperandSize::Word {
+        bytes.push(0x66);
+    }
+    bytes.extend(prefix);
+    bytes.extend(encode_legacy(opcode, reg, size == OperandSize::Qword, rm)?);
+    Some(bytes)
+}
+
+// `opcode /ext` whose only operand is memory, either unsized or of the given size
+pub fn encode_mem(
+    opcode: &[u8],
+    ext: u8,
+    is_wide: bool,
+    mem: &OperandNode,
+    size: Option<OperandSize>,
+    op: &Mnemonic,
+    pos: &Pos,
+) -> Result<Vec<u8>, Error> {
+    match mem {
+        OperandNode::Memory(mem) if mem.size.is_none() || mem.size == size => {}
+        OperandNode::Memory(mem) => return Err(unexpected_operand_size(mem.size.unwrap(), pos)),
+        OperandNode::Register(reg) => return Err(unexpected_register(reg, pos)),
+        _ => return Err(invalid_operands(op, pos)),
+    }
+    encode_legacy(opcode, ext, is_wide, mem).ok_or_else(|| invalid_operands(op, pos))
+}
+
+pub fn rex(w: bool, r: bool, x: bool, b: bool) -> u8 {
+    0x40 | (w as u8) << 3 | (r as u8) << 2 | (x as u8) << 1 | b as u8
+}
+
+fn modrm(mod_bits: u8, reg: u8, rm: u8) -> u8 {
+    mod_bits << 6 | (reg & 7) << 3 | rm & 7
+}
+
+fn sib(scale: u8, index: u8, base: u8) -> u8 {
+    let scale_bits = match scale {
+        1 => 0,
+        2 => 1,
+        4 => 2,
+        _ => 3,
+    };
+    scale_bits << 6 | (index & 7) << 3 | base & 7
+}
+
+// the number of a register, whose bits above the lowest three go in the prefix
+pub fn reg_code(reg: &Register) -> Option<u8> {
+    use Register::*;
+    let code = match reg {
+        Rax | Eax | Ax | Al | Xmm0 | Ymm0 | Zmm0 | K0 | Cr0 | Dr0 | Es => 0,
+        Rcx | Ecx | Cx | Cl | Xmm1 | Ymm1 | Zmm1 | K1 | Dr1 | Cs => 1,
+        Rdx | Edx | Dx | Dl | Xmm2 | Ymm2 | Zmm2 | K2 | Cr2 | Dr2 | Ss => 2,
+        Rbx | Ebx | Bx | Bl | Xmm3 | Ymm3 | Zmm3 | K3 | Cr3 | Dr3 | Ds => 3,
+        Rsp | Esp | Sp | Spl | Ah | Xmm4 | Ymm4 | Zmm4 | K4 | Cr4 | Dr4 | Fs => 4,
+        Rbp | Ebp | Bp | Bpl | Ch | Xmm5 | Ymm5 | Zmm5 | K5 | Dr5 | Gs => 5,
+        Rsi | Esi | Si | Sil | Dh | Xmm6 | Ymm6 | Zmm6 | K6 | Dr6 => 6,
+        Rdi | Edi | Di | Dil | Bh | Xmm7 | Ymm7 | Zmm7 | K7 | Dr7 => 7,
+        R8 | R8d | R8w | R8b | Xmm8 | Ymm8 | Zmm8 | Cr8 => 8,
+        R9 | R9d | R9w | R9b | Xmm9 | Ymm9 | Zmm9 => 9,
+        R10 | R10d | R10w | R10b | Xmm10 | Ymm10 | Zmm10 => 10,
+        R11 | R11d | R11w | R11b | Xmm11 | Ymm11 | Zmm11 => 11,
+        R12 | R12d | R12w | R12b | Xmm12 | Ymm12 | Zmm12 => 12,
+        R13 | R13d | R13w | R13b | Xmm13 | Ymm13 | Zmm13 => 13,
+        R14 | R14d | R14w | R14b | Xmm14 | Ymm14 | Zmm14 => 14,
+        R15 | R15d | R15w | R15b | Xmm15 | Ymm15 | Zmm15 => 15,
+        Xmm16 | Ymm16 | Zmm16 => 16,
+        Xmm17 | Ymm17 | Zmm17 => 17,
+        Xmm18 | Ymm18 | Zmm18 => 18,
+        Xmm19 | Ymm19 | Zmm19 => 19,
+        Xmm20 | Ymm20 | Zmm20 => 20,
+        Xmm21 | Ymm21 | Zmm21 => 21,
+        Xmm22 | Ymm22 | Zmm22 => 22,
+        Xmm23 | Ymm23 | Zmm23 => 23,
+        Xmm24 | Ymm24 | Zmm24 => 24,
+        Xmm25 | Ymm25 | Zmm25 => 25,
+        Xmm26 | Ymm26 | Zmm26 => 26,
+        Xmm27 | Ymm27 | Zmm27 => 27,
+        Xmm28 | Ymm28 | Zmm28 => 28,
+        Xmm29 | Ymm29 | Zmm29 => 29,
+        Xmm30 | Ymm30 | Zmm30 => 30,
+        Xmm31 | Ymm31 | Zmm31 => 31,
+        _ => return None,
+    };
+    Some(code)
+}
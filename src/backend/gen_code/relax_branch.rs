@@ -0,0 +1,98 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::{
+    mnemonic::Mnemonic,
+    operand::{offset::Offset, Operand},
+};
+
+use crate::backend::gen_code::{encode_item, Code, CodeItem, SectionName, SymbolType, Symbols};
+
+// Every branch that has a rel8 form starts out short. Branches whose target
+// does not fit in a signed byte are grown to rel32, which may push other
+// targets out of range, so this repeats until no branch changes its size.
+pub fn relax_branches(symbols: &Symbols, section_name: &SectionName, code: &mut Code) {
+    loop {
+        let addrs = calc_addrs(&code.items);
+        let mut is_changed = false;
+
+        for unresolved_symbol in &code.unresolved_symbols {
+            if !matches!(unresolved_symbol.typ, SymbolType::Jump) {
+                continue;
+            }
+
+            let inst = match code.items.get_mut(unresolved_symbol.item_index).unwrap() {
+                CodeItem::Inst(inst) | CodeItem::Branch16(inst) => inst,
+                _ => panic!(),
+            };
+            let is_short = matches!(inst.operand1, Some(Operand::Offset(Offset::Off8(_))));
+            if !is_short || !has_near_form(&inst.mnemonic) {
+                continue;
+            }
+
+            let symbol = symbols.get(&unresolved_symbol.symbol_name).unwrap();
+            let is_local = !symbol.is_global && symbol.section == *section_name;
+            let fits = match symbol.addr {
+                Some(addr) if is_local => {
+                    let next_addr = addrs[unresolved_symbol.item_index + 1];
+                    let target = addrs[addr] as i64 + unresolved_symbol.addend;
+                    i8::try_from(target - next_addr as i64).is_ok()
+                }
+                _ => false,
+            };
+
+            if !fits {
+                inst.operand1 = Some(Operand::Offset(Offset::Off32(0)));
+                is_changed = true;
+            }
+        }
+
+        if !is_changed {
+            break;
+        }
+    }
+}
+
+pub fn has_short_form(mnemonic: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        mnemonic,
+        Jmp | Ja
+            | Jae
+            | Jb
+            | Jbe
+            | Je
+            | Jg
+            | Jge
+            | Jl
+            | Jle
+            | Jne
+            | Jno
+            | Jnp
+            | Jns
+            | Jo
+            | Jp
+            | Jrcxz
+            | Js
+    )
+}
+
+// instructions whose label operand is a relative branch target
+pub fn is_branch(mnemonic: &Mnemonic) -> bool {
+    has_short_form(mnemonic) || matches!(mnemonic, Mnemonic::Call)
+}
+
+pub fn has_near_form(mnemonic: &Mnemonic) -> bool {
+    !matches!(mnemonic, Mnemonic::Jrcxz)
+}
+
+// returns the address of each item, followed by the size of the whole code
+fn calc_addrs(items: &[CodeItem]) -> Vec<usize> {
+    let mut addrs = Vec::with_capacity(items.len() + 1);
+    let mut addr = 0;
+    addrs.push(addr);
+    for item in items {
+        addr += encode_item(item).len();
+        addrs.push(addr);
+    }
+    addrs
+}
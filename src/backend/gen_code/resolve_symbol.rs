@@ -1,22 +1,59 @@
+use std::convert::TryFrom;
+
 use x86asm::instruction::operand::{offset::Offset, Operand};
 
-use crate::backend::gen_code::{
-    encode_item, Code, CodeItem, Codes, Rela, RelaType, Symbol, SymbolType, Symbols,
+use crate::{
+    backend::gen_code::{
+        encode_item, Code, CodeItem, Codes, Rela, RelaType, Section, SectionName, Symbol,
+        SymbolType, Symbols, Tse,
+    },
+    common::error::{Error, ErrorKind},
 };
 
-pub fn resolve_symbol(symbols: &Symbols, code: &mut Code) -> Vec<Rela> {
+// offset of the symbol address within a `.tse_info` record
+const TSE_ADDR_OFFSET: u32 = 24;
+
+// `relocated_symbols` holds the same symbols with byte addresses instead of item indexes
+pub fn resolve_symbol(
+    symbols: &Symbols,
+    relocated_symbols: &Symbols,
+    code: &mut Code,
+) -> Result<Vec<Rela>, Error> {
     let mut relas = Vec::new();
     for unresolved_symbol in &code.unresolved_symbols {
         let symbol = symbols.get(&unresolved_symbol.symbol_name).unwrap();
         match unresolved_symbol.typ {
             SymbolType::Addr => {
+                // rip points past the bytes that follow the displacement
+                let trailing = unresolved_symbol.trailing;
                 let item_index = unresolved_symbol.item_index + 1;
-                let rela_offset = calc_offset(&code.items, 0, item_index) as u32 - 4;
+                let rela_offset = calc_offset(&code.items, 0, item_index) as u32 - trailing - 4;
+                let (name, addend) = abs_target(relocated_symbols.get(&symbol.name).unwrap());
 
                 relas.push(Rela {
-                    name: symbol.name.to_string(),
+                    name,
                     typ: RelaType::Pc32,
                     offset: rela_offset,
+                    addend: addend + unresolved_symbol.addend - 4 - trailing as i64,
+                });
+            }
+            SymbolType::Abs16 | SymbolType::Abs32 | SymbolType::Abs32S | SymbolType::Abs64 => {
+                let (typ, size) = match unresolved_symbol.typ {
+                    SymbolType::Abs16 => (RelaType::Abs16, 2),
+                    SymbolType::Abs32 => (RelaType::Abs32, 4),
+                    SymbolType::Abs32S => (RelaType::Abs32S, 4),
+                    _ => (RelaType::Abs64, 8),
+                };
+                let item_index = unresolved_symbol.item_index + 1;
+                let end = calc_offset(&code.items, 0, item_index) as u32;
+                let rela_offset = end - unresolved_symbol.trailing - size;
+                let (name, addend) = abs_target(relocated_symbols.get(&symbol.name).unwrap());
+
+                relas.push(Rela {
+                    name,
+                    typ,
+                    offset: rela_offset,
+                    addend: addend + unresolved_symbol.addend,
                 });
             }
             SymbolType::Jump => {
@@ -28,29 +65,130 @@ pub fn resolve_symbol(symbols: &Symbols, code: &mut Code) -> Vec<Rela> {
                     let item_index = unresolved_symbol.item_index + 1;
                     let symbol_addr = symbol.addr.unwrap();
                     calc_offset(&code.items, item_index, symbol_addr)
+                        + unresolved_symbol.addend as i32
                 };
 
-                let item = code.items.get_mut(unresolved_symbol.item_index).unwrap();
-                match item {
-                    CodeItem::Inst(inst) => {
-                        inst.operand1 = Some(Operand::Offset(Offset::Off32(offset)));
-                    }
+                let out_of_range = || {
+                    Error::new(
+                        unresolved_symbol.pos.clone(),
+                        ErrorKind::BranchOutOfRange {
+                            name: symbol.name.to_string(),
+                        },
+                    )
+                };
+
+                let (inst, is_rel16) = match code.items.get_mut(unresolved_symbol.item_index) {
+                    Some(CodeItem::Inst(inst)) => (inst, false),
+                    Some(CodeItem::Branch16(inst)) => (inst, true),
                     _ => panic!(),
-                }
+                };
+                let is_short = matches!(inst.operand1, Some(Operand::Offset(Offset::Off8(_))));
+                let offset = if is_short {
+                    // short branches cannot be relocated
+                    let offset = Some(offset)
+                        .filter(|_| !is_global)
+                        .and_then(|offset| i8::try_from(offset).ok())
+                        .ok_or_else(out_of_range)?;
+                    Offset::Off8(offset)
+                } else {
+                    if is_rel16 && !is_global && i16::try_from(offset).is_err() {
+                        return Err(out_of_range());
+                    }
+                    Offset::Off32(offset)
+                };
+                inst.operand1 = Some(Operand::Offset(offset));
+
                 if is_global {
+                    let (typ, size) = if is_rel16 {
+                        (RelaType::Pc16, 2)
+                    } else {
+                        (RelaType::Plt32, 4)
+                    };
                     let item_index = unresolved_symbol.item_index + 1;
-                    let rela_offset = calc_offset(&code.items, 0, item_index) as u32 - 4;
+                    let rela_offset = calc_offset(&code.items, 0, item_index) as u32 - size;
 
                     relas.push(Rela {
                         name: symbol.name.to_string(),
-                        typ: RelaType::Plt32,
+                        typ,
                         offset: rela_offset,
+                        addend: unresolved_symbol.addend - size as i64,
                     });
                 }
             }
         }
     }
-    relas
+    Ok(relas)
+}
+
+// `.tse_info` holds one 32-byte little-endian record per `.tse` directive:
+//
+//   0x00  i64  offset
+//   0x08  u64  size
+//   0x10  u64  align
+//   0x18  u64  address of the symbol, filled in by an R_X86_64_64 relocation
+pub fn resolve_tses(
+    symbols: &Symbols,
+    codes: &Codes,
+    tses: Vec<Tse>,
+) -> Result<Option<Section>, Error> {
+    if tses.is_empty() {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    let mut relas = Vec::new();
+    for tse in tses {
+        let symbol = symbols.get(&tse.symbol_name).ok_or_else(|| {
+            Error::new(
+                tse.pos.clone(),
+                ErrorKind::UndefinedSymbol {
+                    name: tse.symbol_name.clone(),
+                },
+            )
+        })?;
+
+        let (name, addend) = abs_target(&relocate_symbol(symbol.clone(), codes));
+
+        relas.push(Rela {
+            name,
+            typ: RelaType::Abs64,
+            offset: data.len() as u32 + TSE_ADDR_OFFSET,
+            addend,
+        });
+
+        data.extend_from_slice(&tse.offset.to_le_bytes());
+        data.extend_from_slice(&tse.size.to_le_bytes());
+        data.extend_from_slice(&tse.align.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+    }
+
+    Ok(Some(Section {
+        name: SectionName::TseInfo,
+        data,
+        relas,
+    }))
+}
+
+// returns the symbol and addend that a data relocation refers to,
+// where local labels are relocated against their section symbol
+fn abs_target(symbol: &Symbol) -> (String, i64) {
+    let is_global = symbol.is_global || symbol.addr.is_none();
+    if is_global {
+        (symbol.name.clone(), 0)
+    } else {
+        (
+            symbol.section.as_str().to_string(),
+            symbol.addr.unwrap() as i64,
+        )
+    }
+}
+
+// converts the item index of each defined symbol into a byte address
+pub fn relocate_symbols(symbols: &Symbols, codes: &Codes) -> Symbols {
+    symbols
+        .iter()
+        .map(|(name, symbol)| (name.clone(), relocate_symbol(symbol.clone(), codes)))
+        .collect()
 }
 
 pub fn list_global_symbols(symbols: Symbols, codes: &Codes) -> Vec<Symbol> {
@@ -67,11 +205,12 @@ pub fn list_global_symbols(symbols: Symbols, codes: &Codes) -> Vec<Symbol> {
 }
 
 fn relocate_symbol(mut symbol: Symbol, codes: &Codes) -> Symbol {
-    let code = codes.get(&symbol.section).unwrap();
+    // labels of a section without any code are all at its start
+    let items = codes
+        .get(&symbol.section)
+        .map_or(&[][..], |code| &code.items);
 
-    symbol.addr = symbol
-        .addr
-        .map(|addr| calc_offset(&code.items, 0, addr) as usize);
+    symbol.addr = symbol.addr.map(|addr| calc_offset(items, 0, addr) as usize);
 
     symbol
 }
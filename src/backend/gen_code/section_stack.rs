@@ -0,0 +1,83 @@
+use crate::{
+    backend::gen_code::SectionName,
+    common::{
+        error::{Error, ErrorKind},
+        pos::Pos,
+    },
+    frontend::parser::node::{PseudoOp, PseudoOpArg},
+};
+
+#[derive(Hash, Eq, PartialEq, Clone, Debug)]
+pub struct Subsection {
+    pub name: SectionName,
+    pub number: u32,
+}
+
+impl Subsection {
+    pub fn new(name: SectionName, number: u32) -> Self {
+        Self { name, number }
+    }
+}
+
+// tracks the section that code is emitted to, shared by every pass over the program
+pub struct SectionStack {
+    current: Subsection,
+    previous: Option<Subsection>,
+    // holds the current and previous section saved by `.pushsection`
+    stack: Vec<(Subsection, Option<Subsection>)>,
+}
+
+impl SectionStack {
+    pub fn new() -> Self {
+        Self {
+            current: Subsection::new(SectionName::Text, 0),
+            previous: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> &Subsection {
+        &self.current
+    }
+
+    pub fn switch(&mut self, op: &PseudoOp, args: &[PseudoOpArg], pos: &Pos) -> Result<(), Error> {
+        match op {
+            PseudoOp::Data => self.set(Subsection::new(SectionName::Data, 0)),
+            PseudoOp::Text => self.set(Subsection::new(SectionName::Text, 0)),
+            PseudoOp::PushSection => {
+                self.stack
+                    .push((self.current.clone(), self.previous.clone()));
+
+                let name = SectionName::from(args[0].as_string().as_str());
+                let number = args.get(1).map_or(0, |arg| *arg.as_integer() as u32);
+                self.set(Subsection::new(name, number));
+            }
+            PseudoOp::PopSection => {
+                let (current, previous) = self
+                    .stack
+                    .pop()
+                    .ok_or_else(|| Error::new(pos.clone(), ErrorKind::EmptySectionStack))?;
+                self.current = current;
+                self.previous = previous;
+            }
+            PseudoOp::Previous => {
+                let previous = self
+                    .previous
+                    .take()
+                    .ok_or_else(|| Error::new(pos.clone(), ErrorKind::NoPreviousSection))?;
+                self.set(previous);
+            }
+            PseudoOp::Subsection => {
+                let name = self.current.name.clone();
+                self.set(Subsection::new(name, *args[0].as_integer() as u32));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, next: Subsection) {
+        let prev = std::mem::replace(&mut self.current, next);
+        self.previous = Some(prev);
+    }
+}
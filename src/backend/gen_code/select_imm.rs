@@ -0,0 +1,86 @@
+use std::convert::TryFrom;
+
+use x86asm::instruction::{mnemonic::Mnemonic, operand::immediate::Immediate};
+
+use crate::{
+    backend::gen_code::check_operand::{is_shift, out_of_range},
+    common::{error::Error, pos::Pos},
+    frontend::parser::node::OperandSize,
+};
+
+// Picks the narrowest immediate the instruction can encode the value with.
+// `size` is the size of the destination operand, if it has one.
+pub fn select_imm(
+    op: &Mnemonic,
+    size: Option<OperandSize>,
+    is_reg_dest: bool,
+    value: i64,
+    pos: &Pos,
+) -> Result<Immediate, Error> {
+    use OperandSize::*;
+
+    let imm = if is_shift(op) {
+        imm8(value)
+    } else if matches!(op, Mnemonic::Ret) {
+        u16::try_from(value)
+            .ok()
+            .map(|value| Immediate::Imm16(value as i16))
+    } else if has_imm8_form(op) && i8::try_from(value).is_ok() {
+        Some(Immediate::Imm8(value as i8))
+    } else {
+        match size {
+            Some(Byte) => imm8(value),
+            Some(Word) => imm16(value),
+            Some(Dword) => imm32(value),
+            // 64-bit operations sign-extend imm32, except for `mov r64, imm64`
+            Some(Qword) | None => match i32::try_from(value) {
+                Ok(value) => Some(Immediate::Imm32(value)),
+                Err(_) if matches!(op, Mnemonic::Mov) && is_reg_dest => {
+                    Some(Immediate::Imm64(value))
+                }
+                Err(_) => None,
+            },
+            // check_operands only allows far pointers in memory for far jumps and calls
+            Some(Fword) => unreachable!(),
+            // check_operands only allows vector memory for vector instructions
+            Some(Xmmword) | Some(Ymmword) | Some(Zmmword) => unreachable!(),
+        }
+    };
+
+    imm.ok_or_else(|| out_of_range(value, pos))
+}
+
+// instructions that sign-extend an imm8 to the operand size
+fn has_imm8_form(op: &Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        op,
+        Adc | Add | And | Cmp | IMul | Or | Push | Sbb | Sub | Xor
+    )
+}
+
+// the immediates below accept both signed and unsigned values of their width
+
+fn imm8(value: i64) -> Option<Immediate> {
+    if (-0x80..=0xff).contains(&value) {
+        Some(Immediate::Imm8(value as i8))
+    } else {
+        None
+    }
+}
+
+fn imm16(value: i64) -> Option<Immediate> {
+    if (-0x8000..=0xffff).contains(&value) {
+        Some(Immediate::Imm16(value as i16))
+    } else {
+        None
+    }
+}
+
+fn imm32(value: i64) -> Option<Immediate> {
+    if (-0x8000_0000..=0xffff_ffff).contains(&value) {
+        Some(Immediate::Imm32(value as i32))
+    } else {
+        None
+    }
+}
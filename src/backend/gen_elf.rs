@@ -7,7 +7,6 @@ use elfen::{
     section::{self, SectionData, SectionHeader},
     strtab::Strtab,
     symbol::{self, Symbol},
-    tse::Tse,
 };
 
 use crate::{
@@ -15,6 +14,10 @@ use crate::{
     common::error::Error,
 };
 
+// SHT_LOUSER + 1
+const SHT_TSE_INFO: u32 = 0x8000_0001;
+const TSE_RECORD_SIZE: u64 = 32;
+
 struct ElfGen {
     elf: Elf,
     obj: Object,
@@ -61,7 +64,6 @@ impl ElfGen {
         self.gen_alloc_sections();
         self.gen_symtab_strtab();
         self.gen_rela_sections();
-        self.gen_tse_section();
         self.gen_shstrtab();
     }
 
@@ -70,6 +72,8 @@ impl ElfGen {
             let header = match section.name {
                 SectionName::Data => gen_data_header(),
                 SectionName::Text => gen_text_header(),
+                SectionName::TseInfo => gen_tse_header(),
+                SectionName::Other(_) => gen_other_header(),
             };
 
             let data_raw = std::mem::replace(&mut section.data, Vec::new());
@@ -106,6 +110,10 @@ impl ElfGen {
             symbol_text_section.set_binding(symbol::Binding::Local);
             symbol_text_section.set_index_type(symbol::IndexType::Index(section_index as u16));
             symbols.push(symbol_text_section);
+
+            // local labels are relocated against the section symbol
+            self.symbols
+                .insert(section_name.to_string(), symbols.len() - 1);
         }
 
         // add symbols
@@ -171,10 +179,15 @@ impl ElfGen {
                 .get(&rela_data.name)
                 .expect(&format!("cannot find symbol '{}'", rela_data.name));
             match rela_data.typ {
+                RelaType::Pc16 => rela.set_info(*symbol_index as u64, rel::Type::Pc16),
                 RelaType::Pc32 => rela.set_info(*symbol_index as u64, rel::Type::Pc32),
                 RelaType::Plt32 => rela.set_info(*symbol_index as u64, rel::Type::Plt32),
+                RelaType::Abs16 => rela.set_info(*symbol_index as u64, rel::Type::Abs16),
+                RelaType::Abs32 => rela.set_info(*symbol_index as u64, rel::Type::Abs32),
+                RelaType::Abs32S => rela.set_info(*symbol_index as u64, rel::Type::Abs32S),
+                RelaType::Abs64 => rela.set_info(*symbol_index as u64, rel::Type::Abs64),
             }
-            rela.addend = -4;
+            rela.addend = rela_data.addend;
             relas.push(rela);
         }
 
@@ -184,32 +197,6 @@ impl ElfGen {
         self.elf.add_section(&name, header, data);
     }
 
-    fn gen_tse_section(&mut self) {
-        if self.obj.tses.len() == 0 {
-            return;
-        }
-
-        let mut header = SectionHeader::default();
-        header.set_type(section::Type::Progbits);
-        header.entry_size = size_of::<Tse>() as u64;
-        header.alignment = 8;
-
-        let mut tses = Vec::new();
-        for tse in &self.obj.tses {
-            let symbol_index = *self.symbols.get(&tse.symbol_name).unwrap() as u64;
-            tses.push(Tse {
-                offset: tse.offset,
-                size: tse.size,
-                align: tse.align,
-                symbol_index,
-            });
-        }
-
-        let data = SectionData::Tse(tses);
-
-        self.elf.add_section(".tse_info", header, data);
-    }
-
     fn gen_shstrtab(&mut self) {
         let mut header = SectionHeader::default();
         header.set_type(section::Type::Strtab);
@@ -245,3 +232,19 @@ fn gen_text_header() -> SectionHeader {
     header.alignment = 1;
     header
 }
+
+fn gen_tse_header() -> SectionHeader {
+    let mut header = SectionHeader::default();
+    header.set_type(section::Type::Other(SHT_TSE_INFO));
+    header.entry_size = TSE_RECORD_SIZE;
+    header.alignment = 8;
+    header
+}
+
+fn gen_other_header() -> SectionHeader {
+    let mut header = SectionHeader::default();
+    header.set_type(section::Type::Progbits);
+    header.set_flags(section::Flags::Alloc);
+    header.alignment = 1;
+    header
+}
@@ -5,7 +5,13 @@ use x86asm::instruction::{
     operand::{register::Register, Operand},
 };
 
-use crate::{common::pos::Pos, frontend::lexer::token::TokenKind};
+use crate::{
+    common::pos::Pos,
+    frontend::{
+        lexer::token::{Prefix, TokenKind},
+        parser::node::OperandSize,
+    },
+};
 
 #[derive(Debug)]
 pub enum ErrorKind {
@@ -16,6 +22,9 @@ pub enum ErrorKind {
         expected: Option<TokenKind>,
         actual: TokenKind,
     },
+    InvalidNumber {
+        literal: String,
+    },
     ExpectedInteger {
         actual: TokenKind,
     },
@@ -28,13 +37,55 @@ pub enum ErrorKind {
     UnknownPseudoOp {
         name: String,
     },
+    EmptySectionStack,
+    NoPreviousSection,
+    InvalidSubsection {
+        number: i64,
+    },
+    NegativeSize {
+        size: i64,
+    },
+    InvalidAlignment {
+        align: i64,
+    },
+    InvalidScale {
+        scale: i64,
+    },
+    NoPrecedingLabel,
+    UndefinedSymbol {
+        name: String,
+    },
+    BranchOutOfRange {
+        name: String,
+    },
+    IntegerOutOfRange {
+        value: i64,
+    },
 
     UnexpectedMnemonic {
         actual: Mnemonic,
     },
+    MismatchOperandCount {
+        mnemonic: Mnemonic,
+        actual: usize,
+    },
     UnexpectedOperand {
         actual: Operand,
     },
+    InvalidOperands {
+        mnemonic: Mnemonic,
+    },
+    InvalidPrefix {
+        prefix: Prefix,
+        mnemonic: Mnemonic,
+    },
+    InvalidInMode {
+        mnemonic: Mnemonic,
+        bits: u8,
+    },
+    UnsupportedAddressSize {
+        bits: u8,
+    },
     UnexpectedRegister {
         actual: Register,
     },
@@ -42,6 +93,10 @@ pub enum ErrorKind {
         left: Operand,
         right: Operand,
     },
+    UnexpectedOperandSize {
+        actual: OperandSize,
+    },
+    MissingOperandSize,
 }
 
 impl fmt::Display for ErrorKind {
@@ -56,17 +111,45 @@ impl fmt::Display for ErrorKind {
                 }
                 Ok(())
             }
+            InvalidNumber { literal } => write!(f, "invalid number: '{}'", literal),
             ExpectedInteger { actual } => write!(f, "expected integer, but got {:?}", actual),
             ExpectedString { actual } => write!(f, "expected string, but got {:?}", actual),
             ExpectedIdent { actual } => write!(f, "expected identifier, but got {:?}", actual),
             UnknownPseudoOp { name } => write!(f, "unknown pseudo-op: '{}'", name),
+            EmptySectionStack => write!(f, "'.popsection' without matching '.pushsection'"),
+            NoPreviousSection => write!(f, "no previous section to switch to"),
+            InvalidSubsection { number } => write!(f, "subsection out of range: {}", number),
+            NegativeSize { size } => write!(f, "size must not be negative: {}", size),
+            InvalidAlignment { align } => {
+                write!(f, "alignment must be a power of two: {}", align)
+            }
+            InvalidScale { scale } => write!(f, "scale must be 1, 2, 4 or 8: {}", scale),
+            NoPrecedingLabel => write!(f, "no preceding label to attach to"),
+            UndefinedSymbol { name } => write!(f, "undefined symbol: '{}'", name),
+            BranchOutOfRange { name } => write!(f, "branch target out of range: '{}'", name),
+            IntegerOutOfRange { value } => write!(f, "integer out of range: {}", value),
 
             UnexpectedMnemonic { actual } => write!(f, "unexpected {:?}", actual),
+            MismatchOperandCount { mnemonic, actual } => {
+                write!(f, "{:?} does not take {} operands", mnemonic, actual)
+            }
             UnexpectedOperand { actual } => write!(f, "unexpected {:?}", actual),
+            InvalidOperands { mnemonic } => write!(f, "invalid operands for {:?}", mnemonic),
+            InvalidPrefix { prefix, mnemonic } => {
+                write!(f, "{:?} cannot be used with {:?}", prefix, mnemonic)
+            }
+            InvalidInMode { mnemonic, bits } => {
+                write!(f, "{:?} is not valid in {}-bit mode", mnemonic, bits)
+            }
+            UnsupportedAddressSize { bits } => {
+                write!(f, "{}-bit addresses are not supported", bits)
+            }
             UnexpectedRegister { actual } => write!(f, "unexpected {:?}", actual),
             MismatchOperand { left, right } => {
                 write!(f, "operand type mismatch {:?} and {:?}", left, right)
             }
+            UnexpectedOperandSize { actual } => write!(f, "unexpected {:?} operand", actual),
+            MissingOperandSize => write!(f, "operand size is not specified"),
         }
     }
 }
@@ -10,7 +10,7 @@ use crate::{
     frontend::lexer::token::{Symbol, Token, TokenKind},
 };
 
-use self::token::Keyword;
+use self::token::{Keyword, Prefix};
 
 struct Lexer {
     source: SourceFile,
@@ -52,31 +52,27 @@ impl Lexer {
             tokens.push(self.next_token()?);
         }
 
-        tokens.push(Token {
-            kind: TokenKind::EOF,
-            pos: self.pos.clone(),
-        });
+        tokens.push(Token::new(TokenKind::EOF, self.pos.clone(), String::new()));
 
         Ok(tokens)
     }
 
     fn next_token(&mut self) -> Result<Token, Error> {
         if self.is_eof() {
-            return Ok(Token {
-                kind: TokenKind::EOF,
-                pos: self.pos.clone(),
-            });
+            return Ok(Token::new(TokenKind::EOF, self.pos.clone(), String::new()));
         }
 
         let pos = self.pos.clone();
+        let start = self.source_index;
         let kind = match self.peek_char() {
             '"' => self.consume_string_literal(),
-            x if x.is_digit(10) => self.consume_number(),
+            x if x.is_digit(10) => self.consume_number()?,
             x if is_ident(x) => find_keyword(self.consume_ident()),
             _ => self.consume_symbol()?,
         };
 
-        Ok(Token { kind, pos })
+        let text = self.source.content[start..self.source_index].to_string();
+        Ok(Token::new(kind, pos, text))
     }
 
     fn consume_string_literal(&mut self) -> TokenKind {
@@ -108,15 +104,32 @@ impl Lexer {
         }
     }
 
-    fn consume_number(&mut self) -> TokenKind {
-        let mut result = String::new();
+    // decimal, or hexadecimal and binary after `0x` and `0b`, where values above
+    // i64::MAX wrap around like they do in GAS
+    fn consume_number(&mut self) -> Result<TokenKind, Error> {
+        let pos = self.pos.clone();
+        let mut literal = String::new();
+
+        let prefix = self.source.content[self.source_index..].get(..2);
+        let radix = match prefix.map(str::to_ascii_lowercase).as_deref() {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            literal.push(self.consume_char());
+            literal.push(self.consume_char());
+        }
 
-        while !self.is_eof() && self.peek_char().is_digit(10) {
-            result.push(self.consume_char());
+        while !self.is_eof() && self.peek_char().is_digit(radix) {
+            literal.push(self.consume_char());
         }
 
-        let value = result.parse().unwrap();
-        TokenKind::Integer(value)
+        let digits = if radix == 10 { &literal } else { &literal[2..] };
+        match u64::from_str_radix(digits, radix) {
+            Ok(value) => Ok(TokenKind::Integer(value as i64)),
+            Err(_) => Err(Error::new(pos, ErrorKind::InvalidNumber { literal })),
+        }
     }
 
     fn consume_ident(&mut self) -> TokenKind {
@@ -136,6 +149,9 @@ impl Lexer {
             ']' => Symbol::RBracket,
             '+' => Symbol::Plus,
             '-' => Symbol::Minus,
+            '*' => Symbol::Asterisk,
+            '{' => Symbol::LBrace,
+            '}' => Symbol::RBrace,
             ';' => {
                 self.consume_char();
                 return Ok(self.consume_comment());
@@ -207,35 +223,247 @@ fn find_keyword(ident: TokenKind) -> TokenKind {
         x => return x,
     };
 
-    match name.as_str() {
+    // keywords are case-insensitive, but the names of symbols are not
+    match name.to_ascii_lowercase().as_str() {
         "byte" => TokenKind::Keyword(Keyword::Byte),
+        "word" => TokenKind::Keyword(Keyword::Word),
+        "dword" => TokenKind::Keyword(Keyword::Dword),
+        "qword" => TokenKind::Keyword(Keyword::Qword),
+        "fword" => TokenKind::Keyword(Keyword::Fword),
+        "xmmword" => TokenKind::Keyword(Keyword::Xmmword),
+        "ymmword" => TokenKind::Keyword(Keyword::Ymmword),
+        "zmmword" => TokenKind::Keyword(Keyword::Zmmword),
         "ptr" => TokenKind::Keyword(Keyword::Ptr),
+        "offset" => TokenKind::Keyword(Keyword::Offset),
+
+        "lock" => TokenKind::Prefix(Prefix::Lock),
+        "rep" => TokenKind::Prefix(Prefix::Rep),
+        "repe" | "repz" => TokenKind::Prefix(Prefix::Repe),
+        "repne" | "repnz" => TokenKind::Prefix(Prefix::Repne),
 
+        "adc" => TokenKind::Mnemonic(Mnemonic::Adc),
         "add" => TokenKind::Mnemonic(Mnemonic::Add),
+        "addsd" => TokenKind::Mnemonic(Mnemonic::Addsd),
+        "addss" => TokenKind::Mnemonic(Mnemonic::Addss),
         "and" => TokenKind::Mnemonic(Mnemonic::And),
-        "call" => TokenKind::Mnemonic(Mnemonic::Call),
+        "andn" => TokenKind::Mnemonic(Mnemonic::Andn),
+        "andpd" => TokenKind::Mnemonic(Mnemonic::Andpd),
+        "blsi" => TokenKind::Mnemonic(Mnemonic::Blsi),
+        "blsr" => TokenKind::Mnemonic(Mnemonic::Blsr),
+        "bsf" => TokenKind::Mnemonic(Mnemonic::Bsf),
+        "bsr" => TokenKind::Mnemonic(Mnemonic::Bsr),
+        "bt" => TokenKind::Mnemonic(Mnemonic::Bt),
+        "btc" => TokenKind::Mnemonic(Mnemonic::Btc),
+        "btr" => TokenKind::Mnemonic(Mnemonic::Btr),
+        "bts" => TokenKind::Mnemonic(Mnemonic::Bts),
+        "bzhi" => TokenKind::Mnemonic(Mnemonic::Bzhi),
+        "call" | "lcall" => TokenKind::Mnemonic(Mnemonic::Call),
+        "cbw" => TokenKind::Mnemonic(Mnemonic::Cbw),
+        "cdq" => TokenKind::Mnemonic(Mnemonic::Cdq),
+        "cdqe" => TokenKind::Mnemonic(Mnemonic::Cdqe),
+        "cld" => TokenKind::Mnemonic(Mnemonic::Cld),
+        "cli" => TokenKind::Mnemonic(Mnemonic::Cli),
+        "cmova" | "cmovnbe" => TokenKind::Mnemonic(Mnemonic::Cmova),
+        "cmovae" | "cmovnb" | "cmovnc" => TokenKind::Mnemonic(Mnemonic::Cmovae),
+        "cmovb" | "cmovc" | "cmovnae" => TokenKind::Mnemonic(Mnemonic::Cmovb),
+        "cmovbe" | "cmovna" => TokenKind::Mnemonic(Mnemonic::Cmovbe),
+        "cmove" | "cmovz" => TokenKind::Mnemonic(Mnemonic::Cmove),
+        "cmovg" | "cmovnle" => TokenKind::Mnemonic(Mnemonic::Cmovg),
+        "cmovge" | "cmovnl" => TokenKind::Mnemonic(Mnemonic::Cmovge),
+        "cmovl" | "cmovnge" => TokenKind::Mnemonic(Mnemonic::Cmovl),
+        "cmovle" | "cmovng" => TokenKind::Mnemonic(Mnemonic::Cmovle),
+        "cmovne" | "cmovnz" => TokenKind::Mnemonic(Mnemonic::Cmovne),
+        "cmovno" => TokenKind::Mnemonic(Mnemonic::Cmovno),
+        "cmovnp" | "cmovpo" => TokenKind::Mnemonic(Mnemonic::Cmovnp),
+        "cmovns" => TokenKind::Mnemonic(Mnemonic::Cmovns),
+        "cmovo" => TokenKind::Mnemonic(Mnemonic::Cmovo),
+        "cmovp" | "cmovpe" => TokenKind::Mnemonic(Mnemonic::Cmovp),
+        "cmovs" => TokenKind::Mnemonic(Mnemonic::Cmovs),
         "cmp" => TokenKind::Mnemonic(Mnemonic::Cmp),
+        "cmpsb" => TokenKind::Mnemonic(Mnemonic::Cmpsb),
+        "cmpsd" => TokenKind::Mnemonic(Mnemonic::Cmpsd),
+        "cmpsq" => TokenKind::Mnemonic(Mnemonic::Cmpsq),
+        "cmpsw" => TokenKind::Mnemonic(Mnemonic::Cmpsw),
+        "cmpxchg" => TokenKind::Mnemonic(Mnemonic::Cmpxchg),
+        "cmpxchg16b" => TokenKind::Mnemonic(Mnemonic::Cmpxchg16b),
+        "cmpxchg8b" => TokenKind::Mnemonic(Mnemonic::Cmpxchg8b),
+        "comisd" => TokenKind::Mnemonic(Mnemonic::Comisd),
+        "cpuid" => TokenKind::Mnemonic(Mnemonic::Cpuid),
+        "cqo" => TokenKind::Mnemonic(Mnemonic::Cqo),
+        "cvtsi2sd" => TokenKind::Mnemonic(Mnemonic::Cvtsi2sd),
+        "cvtss2sd" => TokenKind::Mnemonic(Mnemonic::Cvtss2sd),
+        "cvttsd2si" => TokenKind::Mnemonic(Mnemonic::Cvttsd2si),
+        "cwd" => TokenKind::Mnemonic(Mnemonic::Cwd),
+        "cwde" => TokenKind::Mnemonic(Mnemonic::Cwde),
+        "dec" => TokenKind::Mnemonic(Mnemonic::Dec),
+        "div" => TokenKind::Mnemonic(Mnemonic::Div),
+        "divsd" => TokenKind::Mnemonic(Mnemonic::Divsd),
+        "endbr64" => TokenKind::Mnemonic(Mnemonic::Endbr64),
+        "enter" => TokenKind::Mnemonic(Mnemonic::Enter),
         "hlt" => TokenKind::Mnemonic(Mnemonic::Hlt),
         "idiv" => TokenKind::Mnemonic(Mnemonic::IDiv),
         "imul" => TokenKind::Mnemonic(Mnemonic::IMul),
-        "je" => TokenKind::Mnemonic(Mnemonic::Je),
-        "jmp" => TokenKind::Mnemonic(Mnemonic::Jmp),
+        "in" => TokenKind::Mnemonic(Mnemonic::In),
+        "inc" => TokenKind::Mnemonic(Mnemonic::Inc),
+        "int" => TokenKind::Mnemonic(Mnemonic::Int),
+        "int3" => TokenKind::Mnemonic(Mnemonic::Int3),
+        "invlpg" => TokenKind::Mnemonic(Mnemonic::Invlpg),
+        "iretq" => TokenKind::Mnemonic(Mnemonic::Iretq),
+        "ja" | "jnbe" => TokenKind::Mnemonic(Mnemonic::Ja),
+        "jae" | "jnb" | "jnc" => TokenKind::Mnemonic(Mnemonic::Jae),
+        "jb" | "jc" | "jnae" => TokenKind::Mnemonic(Mnemonic::Jb),
+        "jbe" | "jna" => TokenKind::Mnemonic(Mnemonic::Jbe),
+        "je" | "jz" => TokenKind::Mnemonic(Mnemonic::Je),
+        "jg" | "jnle" => TokenKind::Mnemonic(Mnemonic::Jg),
+        "jge" | "jnl" => TokenKind::Mnemonic(Mnemonic::Jge),
+        "jl" | "jnge" => TokenKind::Mnemonic(Mnemonic::Jl),
+        "jle" | "jng" => TokenKind::Mnemonic(Mnemonic::Jle),
+        "jmp" | "ljmp" => TokenKind::Mnemonic(Mnemonic::Jmp),
+        "jne" | "jnz" => TokenKind::Mnemonic(Mnemonic::Jne),
+        "jno" => TokenKind::Mnemonic(Mnemonic::Jno),
+        "jnp" | "jpo" => TokenKind::Mnemonic(Mnemonic::Jnp),
+        "jns" => TokenKind::Mnemonic(Mnemonic::Jns),
+        "jo" => TokenKind::Mnemonic(Mnemonic::Jo),
+        "jp" | "jpe" => TokenKind::Mnemonic(Mnemonic::Jp),
+        "jrcxz" => TokenKind::Mnemonic(Mnemonic::Jrcxz),
+        "js" => TokenKind::Mnemonic(Mnemonic::Js),
         "lea" => TokenKind::Mnemonic(Mnemonic::Lea),
-        "mov" => TokenKind::Mnemonic(Mnemonic::Mov),
+        "leave" => TokenKind::Mnemonic(Mnemonic::Leave),
+        "lfence" => TokenKind::Mnemonic(Mnemonic::Lfence),
+        "lgdt" => TokenKind::Mnemonic(Mnemonic::Lgdt),
+        "lidt" => TokenKind::Mnemonic(Mnemonic::Lidt),
+        "lodsb" => TokenKind::Mnemonic(Mnemonic::Lodsb),
+        "lodsd" => TokenKind::Mnemonic(Mnemonic::Lodsd),
+        "lodsq" => TokenKind::Mnemonic(Mnemonic::Lodsq),
+        "lodsw" => TokenKind::Mnemonic(Mnemonic::Lodsw),
+        "ltr" => TokenKind::Mnemonic(Mnemonic::Ltr),
+        "lzcnt" => TokenKind::Mnemonic(Mnemonic::Lzcnt),
+        "mfence" => TokenKind::Mnemonic(Mnemonic::Mfence),
+        // the width of a movabs immediate is picked from its value like any other mov
+        "mov" | "movabs" => TokenKind::Mnemonic(Mnemonic::Mov),
+        "movaps" => TokenKind::Mnemonic(Mnemonic::Movaps),
+        "movd" => TokenKind::Mnemonic(Mnemonic::Movd),
+        "movq" => TokenKind::Mnemonic(Mnemonic::Movq),
+        "movsb" => TokenKind::Mnemonic(Mnemonic::Movsb),
+        "movsd" => TokenKind::Mnemonic(Mnemonic::Movsd),
+        "movsq" => TokenKind::Mnemonic(Mnemonic::Movsq),
+        "movss" => TokenKind::Mnemonic(Mnemonic::Movss),
+        "movsw" => TokenKind::Mnemonic(Mnemonic::Movsw),
         "movsx" => TokenKind::Mnemonic(Mnemonic::Movsx),
+        "movsxd" => TokenKind::Mnemonic(Mnemonic::Movsxd),
+        "movups" => TokenKind::Mnemonic(Mnemonic::Movups),
+        "movzx" => TokenKind::Mnemonic(Mnemonic::Movzx),
+        "mul" => TokenKind::Mnemonic(Mnemonic::Mul),
+        "mulsd" => TokenKind::Mnemonic(Mnemonic::Mulsd),
+        "neg" => TokenKind::Mnemonic(Mnemonic::Neg),
+        "nop" => TokenKind::Mnemonic(Mnemonic::Nop),
+        "not" => TokenKind::Mnemonic(Mnemonic::Not),
         "or" => TokenKind::Mnemonic(Mnemonic::Or),
+        "out" => TokenKind::Mnemonic(Mnemonic::Out),
+        "pause" => TokenKind::Mnemonic(Mnemonic::Pause),
+        "pdep" => TokenKind::Mnemonic(Mnemonic::Pdep),
+        "pext" => TokenKind::Mnemonic(Mnemonic::Pext),
         "pop" => TokenKind::Mnemonic(Mnemonic::Pop),
+        "popcnt" => TokenKind::Mnemonic(Mnemonic::Popcnt),
+        "prefetchnta" => TokenKind::Mnemonic(Mnemonic::Prefetchnta),
+        "prefetcht0" => TokenKind::Mnemonic(Mnemonic::Prefetcht0),
+        "prefetcht1" => TokenKind::Mnemonic(Mnemonic::Prefetcht1),
+        "prefetcht2" => TokenKind::Mnemonic(Mnemonic::Prefetcht2),
+        "prefetchw" => TokenKind::Mnemonic(Mnemonic::Prefetchw),
         "push" => TokenKind::Mnemonic(Mnemonic::Push),
+        "pxor" => TokenKind::Mnemonic(Mnemonic::Pxor),
+        "rcl" => TokenKind::Mnemonic(Mnemonic::Rcl),
+        "rcr" => TokenKind::Mnemonic(Mnemonic::Rcr),
+        "rdmsr" => TokenKind::Mnemonic(Mnemonic::Rdmsr),
+        "rdtsc" => TokenKind::Mnemonic(Mnemonic::Rdtsc),
+        "rdtscp" => TokenKind::Mnemonic(Mnemonic::Rdtscp),
         "ret" => TokenKind::Mnemonic(Mnemonic::Ret),
-        "sete" => TokenKind::Mnemonic(Mnemonic::Sete),
-        "setg" => TokenKind::Mnemonic(Mnemonic::Setg),
-        "setge" => TokenKind::Mnemonic(Mnemonic::Setge),
-        "setl" => TokenKind::Mnemonic(Mnemonic::Setl),
-        "setle" => TokenKind::Mnemonic(Mnemonic::Setle),
-        "setne" => TokenKind::Mnemonic(Mnemonic::Setne),
+        "retf" | "lret" => TokenKind::Mnemonic(Mnemonic::Retf),
+        "rol" => TokenKind::Mnemonic(Mnemonic::Rol),
+        "ror" => TokenKind::Mnemonic(Mnemonic::Ror),
+        "rorx" => TokenKind::Mnemonic(Mnemonic::Rorx),
+        "sar" => TokenKind::Mnemonic(Mnemonic::Sar),
+        "sarx" => TokenKind::Mnemonic(Mnemonic::Sarx),
+        "sbb" => TokenKind::Mnemonic(Mnemonic::Sbb),
+        "scasb" => TokenKind::Mnemonic(Mnemonic::Scasb),
+        "scasd" => TokenKind::Mnemonic(Mnemonic::Scasd),
+        "scasq" => TokenKind::Mnemonic(Mnemonic::Scasq),
+        "scasw" => TokenKind::Mnemonic(Mnemonic::Scasw),
+        "seta" | "setnbe" => TokenKind::Mnemonic(Mnemonic::Seta),
+        "setae" | "setnb" | "setnc" => TokenKind::Mnemonic(Mnemonic::Setae),
+        "setb" | "setc" | "setnae" => TokenKind::Mnemonic(Mnemonic::Setb),
+        "setbe" | "setna" => TokenKind::Mnemonic(Mnemonic::Setbe),
+        "sete" | "setz" => TokenKind::Mnemonic(Mnemonic::Sete),
+        "setg" | "setnle" => TokenKind::Mnemonic(Mnemonic::Setg),
+        "setge" | "setnl" => TokenKind::Mnemonic(Mnemonic::Setge),
+        "setl" | "setnge" => TokenKind::Mnemonic(Mnemonic::Setl),
+        "setle" | "setng" => TokenKind::Mnemonic(Mnemonic::Setle),
+        "setne" | "setnz" => TokenKind::Mnemonic(Mnemonic::Setne),
+        "setno" => TokenKind::Mnemonic(Mnemonic::Setno),
+        "setnp" | "setpo" => TokenKind::Mnemonic(Mnemonic::Setnp),
+        "setns" => TokenKind::Mnemonic(Mnemonic::Setns),
+        "seto" => TokenKind::Mnemonic(Mnemonic::Seto),
+        "setp" | "setpe" => TokenKind::Mnemonic(Mnemonic::Setp),
+        "sets" => TokenKind::Mnemonic(Mnemonic::Sets),
+        "sfence" => TokenKind::Mnemonic(Mnemonic::Sfence),
+        "shl" | "sal" => TokenKind::Mnemonic(Mnemonic::Shl),
+        "shld" => TokenKind::Mnemonic(Mnemonic::Shld),
+        "shlx" => TokenKind::Mnemonic(Mnemonic::Shlx),
+        "shr" => TokenKind::Mnemonic(Mnemonic::Shr),
+        "shrd" => TokenKind::Mnemonic(Mnemonic::Shrd),
+        "sqrtsd" => TokenKind::Mnemonic(Mnemonic::Sqrtsd),
+        "std" => TokenKind::Mnemonic(Mnemonic::Std),
+        "sti" => TokenKind::Mnemonic(Mnemonic::Sti),
+        "stosb" => TokenKind::Mnemonic(Mnemonic::Stosb),
+        "stosd" => TokenKind::Mnemonic(Mnemonic::Stosd),
+        "stosq" => TokenKind::Mnemonic(Mnemonic::Stosq),
+        "stosw" => TokenKind::Mnemonic(Mnemonic::Stosw),
         "sub" => TokenKind::Mnemonic(Mnemonic::Sub),
+        "subsd" => TokenKind::Mnemonic(Mnemonic::Subsd),
+        "swapgs" => TokenKind::Mnemonic(Mnemonic::Swapgs),
         "syscall" => TokenKind::Mnemonic(Mnemonic::Syscall),
+        "sysret" => TokenKind::Mnemonic(Mnemonic::Sysret),
+        "sysretq" => TokenKind::Mnemonic(Mnemonic::Sysretq),
+        "test" => TokenKind::Mnemonic(Mnemonic::Test),
+        "tzcnt" => TokenKind::Mnemonic(Mnemonic::Tzcnt),
+        "ucomisd" => TokenKind::Mnemonic(Mnemonic::Ucomisd),
+        "ud2" => TokenKind::Mnemonic(Mnemonic::Ud2),
+        "vaddpd" => TokenKind::Mnemonic(Mnemonic::Vaddpd),
+        "vaddps" => TokenKind::Mnemonic(Mnemonic::Vaddps),
+        "vbroadcastss" => TokenKind::Mnemonic(Mnemonic::Vbroadcastss),
+        "vfmadd231pd" => TokenKind::Mnemonic(Mnemonic::Vfmadd231pd),
+        "vfmadd231ps" => TokenKind::Mnemonic(Mnemonic::Vfmadd231ps),
+        "vmovaps" => TokenKind::Mnemonic(Mnemonic::Vmovaps),
+        "vmovdqa" => TokenKind::Mnemonic(Mnemonic::Vmovdqa),
+        "vmovdqa32" => TokenKind::Mnemonic(Mnemonic::Vmovdqa32),
+        "vmovdqa64" => TokenKind::Mnemonic(Mnemonic::Vmovdqa64),
+        "vmovdqu" => TokenKind::Mnemonic(Mnemonic::Vmovdqu),
+        "vmovdqu32" => TokenKind::Mnemonic(Mnemonic::Vmovdqu32),
+        "vmovdqu64" => TokenKind::Mnemonic(Mnemonic::Vmovdqu64),
+        "vmovups" => TokenKind::Mnemonic(Mnemonic::Vmovups),
+        "vmulpd" => TokenKind::Mnemonic(Mnemonic::Vmulpd),
+        "vmulps" => TokenKind::Mnemonic(Mnemonic::Vmulps),
+        "vpaddd" => TokenKind::Mnemonic(Mnemonic::Vpaddd),
+        "vpaddq" => TokenKind::Mnemonic(Mnemonic::Vpaddq),
+        "vpand" => TokenKind::Mnemonic(Mnemonic::Vpand),
+        "vpcmpeqb" => TokenKind::Mnemonic(Mnemonic::Vpcmpeqb),
+        "vpcmpeqd" => TokenKind::Mnemonic(Mnemonic::Vpcmpeqd),
+        "vpmovmskb" => TokenKind::Mnemonic(Mnemonic::Vpmovmskb),
+        "vpor" => TokenKind::Mnemonic(Mnemonic::Vpor),
+        "vpsubd" => TokenKind::Mnemonic(Mnemonic::Vpsubd),
+        "vpxor" => TokenKind::Mnemonic(Mnemonic::Vpxor),
+        "vpxord" => TokenKind::Mnemonic(Mnemonic::Vpxord),
+        "vpxorq" => TokenKind::Mnemonic(Mnemonic::Vpxorq),
+        "vxorps" => TokenKind::Mnemonic(Mnemonic::Vxorps),
+        "vzeroupper" => TokenKind::Mnemonic(Mnemonic::Vzeroupper),
+        "wbinvd" => TokenKind::Mnemonic(Mnemonic::Wbinvd),
+        "wrmsr" => TokenKind::Mnemonic(Mnemonic::Wrmsr),
+        "xadd" => TokenKind::Mnemonic(Mnemonic::Xadd),
+        "xchg" => TokenKind::Mnemonic(Mnemonic::Xchg),
         "xor" => TokenKind::Mnemonic(Mnemonic::Xor),
+        "xorps" => TokenKind::Mnemonic(Mnemonic::Xorps),
+        "xrstor" => TokenKind::Mnemonic(Mnemonic::Xrstor),
+        "xsave" => TokenKind::Mnemonic(Mnemonic::Xsave),
 
         "rax" => TokenKind::Register(Register::Rax),
         "rcx" => TokenKind::Register(Register::Rcx),
@@ -262,6 +490,31 @@ fn find_keyword(ident: TokenKind) -> TokenKind {
         "ebp" => TokenKind::Register(Register::Ebp),
         "esi" => TokenKind::Register(Register::Esi),
         "edi" => TokenKind::Register(Register::Edi),
+        "r8d" => TokenKind::Register(Register::R8d),
+        "r9d" => TokenKind::Register(Register::R9d),
+        "r10d" => TokenKind::Register(Register::R10d),
+        "r11d" => TokenKind::Register(Register::R11d),
+        "r12d" => TokenKind::Register(Register::R12d),
+        "r13d" => TokenKind::Register(Register::R13d),
+        "r14d" => TokenKind::Register(Register::R14d),
+        "r15d" => TokenKind::Register(Register::R15d),
+
+        "ax" => TokenKind::Register(Register::Ax),
+        "cx" => TokenKind::Register(Register::Cx),
+        "dx" => TokenKind::Register(Register::Dx),
+        "bx" => TokenKind::Register(Register::Bx),
+        "sp" => TokenKind::Register(Register::Sp),
+        "bp" => TokenKind::Register(Register::Bp),
+        "si" => TokenKind::Register(Register::Si),
+        "di" => TokenKind::Register(Register::Di),
+        "r8w" => TokenKind::Register(Register::R8w),
+        "r9w" => TokenKind::Register(Register::R9w),
+        "r10w" => TokenKind::Register(Register::R10w),
+        "r11w" => TokenKind::Register(Register::R11w),
+        "r12w" => TokenKind::Register(Register::R12w),
+        "r13w" => TokenKind::Register(Register::R13w),
+        "r14w" => TokenKind::Register(Register::R14w),
+        "r15w" => TokenKind::Register(Register::R15w),
 
         "al" => TokenKind::Register(Register::Al),
         "cl" => TokenKind::Register(Register::Cl),
@@ -279,9 +532,143 @@ fn find_keyword(ident: TokenKind) -> TokenKind {
         "r13b" => TokenKind::Register(Register::R13b),
         "r14b" => TokenKind::Register(Register::R14b),
         "r15b" => TokenKind::Register(Register::R15b),
+        "ah" => TokenKind::Register(Register::Ah),
+        "ch" => TokenKind::Register(Register::Ch),
+        "dh" => TokenKind::Register(Register::Dh),
+        "bh" => TokenKind::Register(Register::Bh),
 
         "rip" => TokenKind::Register(Register::Rip),
 
+        "xmm0" => TokenKind::Register(Register::Xmm0),
+        "xmm1" => TokenKind::Register(Register::Xmm1),
+        "xmm2" => TokenKind::Register(Register::Xmm2),
+        "xmm3" => TokenKind::Register(Register::Xmm3),
+        "xmm4" => TokenKind::Register(Register::Xmm4),
+        "xmm5" => TokenKind::Register(Register::Xmm5),
+        "xmm6" => TokenKind::Register(Register::Xmm6),
+        "xmm7" => TokenKind::Register(Register::Xmm7),
+        "xmm8" => TokenKind::Register(Register::Xmm8),
+        "xmm9" => TokenKind::Register(Register::Xmm9),
+        "xmm10" => TokenKind::Register(Register::Xmm10),
+        "xmm11" => TokenKind::Register(Register::Xmm11),
+        "xmm12" => TokenKind::Register(Register::Xmm12),
+        "xmm13" => TokenKind::Register(Register::Xmm13),
+        "xmm14" => TokenKind::Register(Register::Xmm14),
+        "xmm15" => TokenKind::Register(Register::Xmm15),
+        "xmm16" => TokenKind::Register(Register::Xmm16),
+        "xmm17" => TokenKind::Register(Register::Xmm17),
+        "xmm18" => TokenKind::Register(Register::Xmm18),
+        "xmm19" => TokenKind::Register(Register::Xmm19),
+        "xmm20" => TokenKind::Register(Register::Xmm20),
+        "xmm21" => TokenKind::Register(Register::Xmm21),
+        "xmm22" => TokenKind::Register(Register::Xmm22),
+        "xmm23" => TokenKind::Register(Register::Xmm23),
+        "xmm24" => TokenKind::Register(Register::Xmm24),
+        "xmm25" => TokenKind::Register(Register::Xmm25),
+        "xmm26" => TokenKind::Register(Register::Xmm26),
+        "xmm27" => TokenKind::Register(Register::Xmm27),
+        "xmm28" => TokenKind::Register(Register::Xmm28),
+        "xmm29" => TokenKind::Register(Register::Xmm29),
+        "xmm30" => TokenKind::Register(Register::Xmm30),
+        "xmm31" => TokenKind::Register(Register::Xmm31),
+
+        "ymm0" => TokenKind::Register(Register::Ymm0),
+        "ymm1" => TokenKind::Register(Register::Ymm1),
+        "ymm2" => TokenKind::Register(Register::Ymm2),
+        "ymm3" => TokenKind::Register(Register::Ymm3),
+        "ymm4" => TokenKind::Register(Register::Ymm4),
+        "ymm5" => TokenKind::Register(Register::Ymm5),
+        "ymm6" => TokenKind::Register(Register::Ymm6),
+        "ymm7" => TokenKind::Register(Register::Ymm7),
+        "ymm8" => TokenKind::Register(Register::Ymm8),
+        "ymm9" => TokenKind::Register(Register::Ymm9),
+        "ymm10" => TokenKind::Register(Register::Ymm10),
+        "ymm11" => TokenKind::Register(Register::Ymm11),
+        "ymm12" => TokenKind::Register(Register::Ymm12),
+        "ymm13" => TokenKind::Register(Register::Ymm13),
+        "ymm14" => TokenKind::Register(Register::Ymm14),
+        "ymm15" => TokenKind::Register(Register::Ymm15),
+        "ymm16" => TokenKind::Register(Register::Ymm16),
+        "ymm17" => TokenKind::Register(Register::Ymm17),
+        "ymm18" => TokenKind::Register(Register::Ymm18),
+        "ymm19" => TokenKind::Register(Register::Ymm19),
+        "ymm20" => TokenKind::Register(Register::Ymm20),
+        "ymm21" => TokenKind::Register(Register::Ymm21),
+        "ymm22" => TokenKind::Register(Register::Ymm22),
+        "ymm23" => TokenKind::Register(Register::Ymm23),
+        "ymm24" => TokenKind::Register(Register::Ymm24),
+        "ymm25" => TokenKind::Register(Register::Ymm25),
+        "ymm26" => TokenKind::Register(Register::Ymm26),
+        "ymm27" => TokenKind::Register(Register::Ymm27),
+        "ymm28" => TokenKind::Register(Register::Ymm28),
+        "ymm29" => TokenKind::Register(Register::Ymm29),
+        "ymm30" => TokenKind::Register(Register::Ymm30),
+        "ymm31" => TokenKind::Register(Register::Ymm31),
+
+        "zmm0" => TokenKind::Register(Register::Zmm0),
+        "zmm1" => TokenKind::Register(Register::Zmm1),
+        "zmm2" => TokenKind::Register(Register::Zmm2),
+        "zmm3" => TokenKind::Register(Register::Zmm3),
+        "zmm4" => TokenKind::Register(Register::Zmm4),
+        "zmm5" => TokenKind::Register(Register::Zmm5),
+        "zmm6" => TokenKind::Register(Register::Zmm6),
+        "zmm7" => TokenKind::Register(Register::Zmm7),
+        "zmm8" => TokenKind::Register(Register::Zmm8),
+        "zmm9" => TokenKind::Register(Register::Zmm9),
+        "zmm10" => TokenKind::Register(Register::Zmm10),
+        "zmm11" => TokenKind::Register(Register::Zmm11),
+        "zmm12" => TokenKind::Register(Register::Zmm12),
+        "zmm13" => TokenKind::Register(Register::Zmm13),
+        "zmm14" => TokenKind::Register(Register::Zmm14),
+        "zmm15" => TokenKind::Register(Register::Zmm15),
+        "zmm16" => TokenKind::Register(Register::Zmm16),
+        "zmm17" => TokenKind::Register(Register::Zmm17),
+        "zmm18" => TokenKind::Register(Register::Zmm18),
+        "zmm19" => TokenKind::Register(Register::Zmm19),
+        "zmm20" => TokenKind::Register(Register::Zmm20),
+        "zmm21" => TokenKind::Register(Register::Zmm21),
+        "zmm22" => TokenKind::Register(Register::Zmm22),
+        "zmm23" => TokenKind::Register(Register::Zmm23),
+        "zmm24" => TokenKind::Register(Register::Zmm24),
+        "zmm25" => TokenKind::Register(Register::Zmm25),
+        "zmm26" => TokenKind::Register(Register::Zmm26),
+        "zmm27" => TokenKind::Register(Register::Zmm27),
+        "zmm28" => TokenKind::Register(Register::Zmm28),
+        "zmm29" => TokenKind::Register(Register::Zmm29),
+        "zmm30" => TokenKind::Register(Register::Zmm30),
+        "zmm31" => TokenKind::Register(Register::Zmm31),
+
+        "es" => TokenKind::Register(Register::Es),
+        "cs" => TokenKind::Register(Register::Cs),
+        "ss" => TokenKind::Register(Register::Ss),
+        "ds" => TokenKind::Register(Register::Ds),
+        "fs" => TokenKind::Register(Register::Fs),
+        "gs" => TokenKind::Register(Register::Gs),
+
+        "k0" => TokenKind::Register(Register::K0),
+        "k1" => TokenKind::Register(Register::K1),
+        "k2" => TokenKind::Register(Register::K2),
+        "k3" => TokenKind::Register(Register::K3),
+        "k4" => TokenKind::Register(Register::K4),
+        "k5" => TokenKind::Register(Register::K5),
+        "k6" => TokenKind::Register(Register::K6),
+        "k7" => TokenKind::Register(Register::K7),
+
+        "cr0" => TokenKind::Register(Register::Cr0),
+        "cr2" => TokenKind::Register(Register::Cr2),
+        "cr3" => TokenKind::Register(Register::Cr3),
+        "cr4" => TokenKind::Register(Register::Cr4),
+        "cr8" => TokenKind::Register(Register::Cr8),
+
+        "dr0" => TokenKind::Register(Register::Dr0),
+        "dr1" => TokenKind::Register(Register::Dr1),
+        "dr2" => TokenKind::Register(Register::Dr2),
+        "dr3" => TokenKind::Register(Register::Dr3),
+        "dr4" => TokenKind::Register(Register::Dr4),
+        "dr5" => TokenKind::Register(Register::Dr5),
+        "dr6" => TokenKind::Register(Register::Dr6),
+        "dr7" => TokenKind::Register(Register::Dr7),
+
         _ => ident,
     }
 }
@@ -6,23 +6,26 @@ use crate::common::pos::Pos;
 pub struct Token {
     pub kind: TokenKind,
     pub pos: Pos,
+    // the token as written, which keeps the spelling of keywords
+    pub text: String,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, pos: Pos) -> Self {
-        Self { kind, pos }
+    pub fn new(kind: TokenKind, pos: Pos, text: String) -> Self {
+        Self { kind, pos, text }
     }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum TokenKind {
-    Integer(i32),
+    Integer(i64),
     String(String),
     Ident(String),
     Symbol(Symbol),
     Keyword(Keyword),
     Mnemonic(Mnemonic),
     Register(Register),
+    Prefix(Prefix),
     Comment(String),
     EOF,
 }
@@ -35,10 +38,29 @@ pub enum Symbol {
     RBracket,
     Plus,
     Minus,
+    Asterisk,
+    LBrace,
+    RBrace,
 }
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum Keyword {
     Byte,
+    Word,
+    Dword,
+    Qword,
+    Fword,
+    Xmmword,
+    Ymmword,
+    Zmmword,
     Ptr,
+    Offset,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Prefix {
+    Lock,
+    Rep,
+    Repe,
+    Repne,
 }
@@ -1,13 +1,17 @@
 pub mod node;
 
-use x86asm::instruction::mnemonic;
+use x86asm::instruction::{
+    mnemonic::{self, Mnemonic},
+    operand::register::Register,
+};
 
 use crate::{
     common::error::{Error, ErrorKind},
     frontend::{
         lexer::token::{Keyword, Symbol, Token, TokenKind},
         parser::node::{
-            DispNode, InstructionNode, MemoryNode, OperandNode, Program, PseudoOp, PseudoOpArg,
+            DispNode, IndexNode, InstructionNode, MemoryNode, OperandNode, OperandSize, Program,
+            PseudoOp, PseudoOpArg, Rounding, Statement,
         },
     },
 };
@@ -28,7 +32,7 @@ impl Parser {
     }
 
     fn parse(&mut self) -> Result<Program, Error> {
-        let mut insts = Vec::new();
+        let mut stmts = Vec::new();
         loop {
             if self.is_eof() {
                 break;
@@ -39,117 +43,397 @@ impl Parser {
                 continue;
             }
 
-            if !matches!(self.peek().kind, TokenKind::Ident(_)) {
-                insts.push(self.parse_inst()?);
-                continue;
+            let pos = self.peek().pos;
+            let node = self.parse_stmt()?;
+            stmts.push(Statement { node, pos });
+        }
+        Ok(Program { stmts })
+    }
+
+    fn parse_stmt(&mut self) -> Result<InstructionNode, Error> {
+        // a label can be named by anything that can refer to it, like `Out:`,
+        // but registers and keywords are operands and so cannot name one
+        if self.peek_next().kind == TokenKind::Symbol(Symbol::Colon) {
+            let token = self.consume();
+            if !is_symbol_name(&token.kind) {
+                return Err(unexpected(token));
             }
+            self.consume();
+            return Ok(InstructionNode::Label(token.text));
+        }
+
+        if !matches!(self.peek().kind, TokenKind::Ident(_)) {
+            return self.parse_inst();
+        }
 
-            let ident_token = self.peek().clone();
-            let ident = self.consume_ident()?;
+        let ident_token = self.consume();
+        if ident_token.text.starts_with('.') {
+            return self.parse_pseudop(ident_token);
+        }
 
-            if self.peek().kind == TokenKind::Symbol(Symbol::Colon) {
+        Err(unexpected(ident_token))
+    }
+
+    fn parse_inst(&mut self) -> Result<InstructionNode, Error> {
+        let prefix = match self.peek().kind {
+            TokenKind::Prefix(prefix) => {
                 self.consume();
-                insts.push(InstructionNode::Label(ident));
-                continue;
+                Some(prefix)
             }
+            _ => None,
+        };
 
-            if ident.starts_with('.') {
-                insts.push(self.parse_pseudop(ident_token)?);
-                continue;
+        let token = self.consume();
+        let mnemonic = match token.kind {
+            TokenKind::Mnemonic(mnemonic) => mnemonic,
+            _ => return Err(unexpected(token)),
+        };
+
+        let mut operands = self.parse_operands(token.pos.line)?;
+
+        // `ljmp` and `lcall` are always far, so they take memory as `fword ptr`
+        let is_far = ["ljmp", "lcall"]
+            .iter()
+            .any(|far| token.text.eq_ignore_ascii_case(far));
+        if is_far {
+            for opr in operands.iter_mut() {
+                match opr {
+                    OperandNode::Memory(mem) if mem.size.is_none() => {
+                        mem.size = Some(OperandSize::Fword)
+                    }
+                    OperandNode::Memory(mem) if mem.size == Some(OperandSize::Fword) => {}
+                    OperandNode::FarPointer { .. } => {}
+                    _ => {
+                        return Err(Error::new(
+                            token.pos,
+                            ErrorKind::InvalidOperands { mnemonic },
+                        ))
+                    }
+                }
             }
+        }
 
-            return Err(unexpected(ident_token));
+        // a rounding mode is not counted as an operand
+        let count = operands
+            .iter()
+            .filter(|opr| !matches!(opr, OperandNode::Rounding(_)))
+            .count();
+        if !arities(&mnemonic).contains(&count) {
+            return Err(Error::new(
+                token.pos,
+                ErrorKind::MismatchOperandCount {
+                    mnemonic,
+                    actual: count,
+                },
+            ));
+        }
+
+        // a shift or rotate by one can leave out the count
+        if is_shift_by_one(&mnemonic, &operands) {
+            operands.push(OperandNode::Immidiate(1));
         }
-        Ok(Program { insts })
+
+        Ok(InstructionNode::Op(prefix, mnemonic, operands))
     }
 
-    fn parse_inst(&mut self) -> Result<InstructionNode, Error> {
-        let token = self.consume().clone();
-        match token.kind {
-            TokenKind::Mnemonic(mnemonic) => match mnemonic.typ() {
-                mnemonic::Type::Nullary => Ok(InstructionNode::NullaryOp(mnemonic)),
-                mnemonic::Type::Unary => {
-                    let operand1 = self.parse_operand()?;
-                    Ok(InstructionNode::UnaryOp(mnemonic, operand1))
-                }
-                mnemonic::Type::Binary => {
-                    let operand1 = self.parse_operand()?;
-                    self.expect(TokenKind::Symbol(Symbol::Comma))?;
-                    let operand2 = self.parse_operand()?;
-                    Ok(InstructionNode::BinaryOp(mnemonic, operand1, operand2))
-                }
-            },
-            _ => Err(unexpected(token)),
+    fn parse_operands(&mut self, line: usize) -> Result<Vec<OperandNode>, Error> {
+        let mut operands = Vec::new();
+
+        // operands start on the line of the mnemonic
+        let token = self.peek();
+        if token.pos.line != line || matches!(token.kind, TokenKind::Comment(_) | TokenKind::EOF) {
+            return Ok(operands);
         }
+
+        operands.push(self.parse_operand()?);
+        while self.peek().kind == TokenKind::Symbol(Symbol::Comma) {
+            self.consume();
+            operands.push(self.parse_operand()?);
+        }
+
+        Ok(operands)
     }
 
     fn parse_operand(&mut self) -> Result<OperandNode, Error> {
+        let opr = self.parse_plain_operand()?;
+        match opr {
+            OperandNode::Register(_) | OperandNode::Memory(_) => self.parse_decorations(opr),
+            opr => Ok(opr),
+        }
+    }
+
+    fn parse_plain_operand(&mut self) -> Result<OperandNode, Error> {
         let token = self.consume();
         match token.kind {
             TokenKind::Symbol(Symbol::Plus) => Ok(OperandNode::Immidiate(self.consume_integer()?)),
             TokenKind::Symbol(Symbol::Minus) => {
                 Ok(OperandNode::Immidiate(-self.consume_integer()?))
             }
+            TokenKind::Integer(segment) if self.peek().kind == TokenKind::Symbol(Symbol::Colon) => {
+                self.consume();
+                self.parse_far_pointer(segment)
+            }
             TokenKind::Integer(value) => Ok(OperandNode::Immidiate(value)),
-            TokenKind::Ident(name) => Ok(OperandNode::Label(name.to_owned())),
+            // `far [mem]` and `far ptr [mem]` mean `fword ptr [mem]`, while `far` alone is a label
+            TokenKind::Ident(ref name)
+                if name.eq_ignore_ascii_case("far")
+                    && matches!(
+                        self.peek().kind,
+                        TokenKind::Keyword(Keyword::Ptr) | TokenKind::Symbol(Symbol::LBracket)
+                    ) =>
+            {
+                if self.peek().kind == TokenKind::Keyword(Keyword::Ptr) {
+                    self.consume();
+                }
+                self.expect(TokenKind::Symbol(Symbol::LBracket))?;
+                self.parse_operand_address(Some(OperandSize::Fword))
+            }
+            _ if is_symbol_name(&token.kind) => self.parse_operand_label(token.text),
             TokenKind::Register(reg) => Ok(OperandNode::Register(reg.to_owned())),
-            TokenKind::Symbol(Symbol::LBracket) => self.parse_operand_address(),
-            // TODO
-            TokenKind::Keyword(Keyword::Byte) => {
+            TokenKind::Symbol(Symbol::LBracket) => self.parse_operand_address(None),
+            TokenKind::Symbol(Symbol::LBrace) => self.parse_rounding(),
+            TokenKind::Keyword(Keyword::Offset) => {
+                let token = self.consume();
+                match token.kind {
+                    _ if is_symbol_name(&token.kind) => self.parse_operand_label(token.text),
+                    _ => Err(unexpected(token)),
+                }
+            }
+            TokenKind::Keyword(keyword) => {
+                let size = match keyword {
+                    Keyword::Byte => OperandSize::Byte,
+                    Keyword::Word => OperandSize::Word,
+                    Keyword::Dword => OperandSize::Dword,
+                    Keyword::Qword => OperandSize::Qword,
+                    Keyword::Fword => OperandSize::Fword,
+                    Keyword::Xmmword => OperandSize::Xmmword,
+                    Keyword::Ymmword => OperandSize::Ymmword,
+                    Keyword::Zmmword => OperandSize::Zmmword,
+                    _ => return Err(unexpected(token)),
+                };
                 self.expect(TokenKind::Keyword(Keyword::Ptr))?;
                 self.expect(TokenKind::Symbol(Symbol::LBracket))?;
-                self.parse_operand_address()
+                self.parse_operand_address(Some(size))
             }
             _ => Err(unexpected(token)),
         }
     }
 
-    fn parse_operand_address(&mut self) -> Result<OperandNode, Error> {
+    // `{k1}`, `{k1}{z}` or `{1toN}` after a register or memory operand
+    fn parse_decorations(&mut self, mut opr: OperandNode) -> Result<OperandNode, Error> {
+        while self.peek().kind == TokenKind::Symbol(Symbol::LBrace) {
+            self.consume();
+
+            let token = self.consume();
+            opr = match (token.kind.clone(), opr) {
+                (TokenKind::Register(mask), opr @ OperandNode::Register(_))
+                | (TokenKind::Register(mask), opr @ OperandNode::Memory(_)) => {
+                    OperandNode::Masked {
+                        opr: Box::new(opr),
+                        mask,
+                        is_zeroing: false,
+                    }
+                }
+                (TokenKind::Ident(ref z), OperandNode::Masked { opr, mask, .. })
+                    if z.eq_ignore_ascii_case("z") =>
+                {
+                    OperandNode::Masked {
+                        opr,
+                        mask,
+                        is_zeroing: true,
+                    }
+                }
+                (TokenKind::Integer(1), OperandNode::Memory(mut mem))
+                    if mem.broadcast.is_none() =>
+                {
+                    let token = self.consume();
+                    mem.broadcast = match token.kind {
+                        TokenKind::Ident(ref name) => match name.to_ascii_lowercase().as_str() {
+                            "to2" => Some(2),
+                            "to4" => Some(4),
+                            "to8" => Some(8),
+                            "to16" => Some(16),
+                            _ => return Err(unexpected(token)),
+                        },
+                        _ => return Err(unexpected(token)),
+                    };
+                    OperandNode::Memory(mem)
+                }
+                _ => return Err(unexpected(token)),
+            };
+
+            self.expect(TokenKind::Symbol(Symbol::RBrace))?;
+        }
+        Ok(opr)
+    }
+
+    // `{rn-sae}`, `{rd-sae}`, `{ru-sae}` or `{rz-sae}`
+    fn parse_rounding(&mut self) -> Result<OperandNode, Error> {
         let token = self.consume();
-        let base = match token.kind {
-            TokenKind::Register(reg) => reg.clone(),
+        let rounding = match token.kind {
+            TokenKind::Ident(ref name) => match name.to_ascii_lowercase().as_str() {
+                "rn" => Rounding::Nearest,
+                "rd" => Rounding::Down,
+                "ru" => Rounding::Up,
+                "rz" => Rounding::Zero,
+                _ => return Err(unexpected(token)),
+            },
             _ => return Err(unexpected(token)),
         };
 
-        // TODO
-        let token = self.peek();
-        let disp = match token.kind {
-            TokenKind::Symbol(Symbol::RBracket) => None,
-            TokenKind::Symbol(Symbol::Minus) => {
+        self.expect(TokenKind::Symbol(Symbol::Minus))?;
+        let token = self.consume();
+        match token.kind {
+            TokenKind::Ident(ref name) if name.eq_ignore_ascii_case("sae") => {}
+            _ => return Err(unexpected(token)),
+        }
+        self.expect(TokenKind::Symbol(Symbol::RBrace))?;
+
+        Ok(OperandNode::Rounding(rounding))
+    }
+
+    // `label`, `label+n` or `label-n`
+    fn parse_operand_label(&mut self, name: String) -> Result<OperandNode, Error> {
+        let addend = self.parse_addend()?;
+        Ok(OperandNode::Label { name, addend })
+    }
+
+    // the `+n` or `-n` after a label
+    fn parse_addend(&mut self) -> Result<i64, Error> {
+        let addend = match self.peek().kind {
+            TokenKind::Symbol(Symbol::Plus) => {
                 self.consume();
-                Some(DispNode::Immediate(-self.consume_integer()?))
+                self.consume_integer()?
             }
-            TokenKind::Symbol(Symbol::Plus) => {
+            TokenKind::Symbol(Symbol::Minus) => {
                 self.consume();
-                let token = self.consume();
-                match token.kind {
-                    TokenKind::Integer(value) => Some(DispNode::Immediate(value)),
-                    TokenKind::Ident(name) => Some(DispNode::Label(name)),
-                    _ => return Err(unexpected(token)),
+                -self.consume_integer()?
+            }
+            _ => 0,
+        };
+        Ok(addend)
+    }
+
+    // the offset after `segment:`, which is an integer or a label
+    fn parse_far_pointer(&mut self, segment: i64) -> Result<OperandNode, Error> {
+        let token = self.consume();
+        let offset = match token.kind {
+            TokenKind::Integer(value) => DispNode::Immediate(value),
+            _ if is_symbol_name(&token.kind) => DispNode::Label {
+                name: token.text,
+                addend: self.parse_addend()?,
+            },
+            _ => return Err(unexpected(token)),
+        };
+        Ok(OperandNode::FarPointer { segment, offset })
+    }
+
+    // `[base + index*scale + disp]`, where the displacement may be a label with an addend,
+    // and either part may be missing as in `[label]` or `[label + index*8]`
+    fn parse_operand_address(&mut self, size: Option<OperandSize>) -> Result<OperandNode, Error> {
+        let mut base = None;
+        let mut index = None;
+        let mut label = None;
+        let mut disp = None;
+
+        let mut is_negative = self.peek().kind == TokenKind::Symbol(Symbol::Minus);
+        if is_negative {
+            self.consume();
+        }
+        loop {
+            let token = self.consume();
+            match token.kind {
+                TokenKind::Integer(value) => {
+                    let value = if is_negative { -value } else { value };
+                    disp = Some(disp.unwrap_or(0) + value);
+                }
+                TokenKind::Register(_) if !is_negative => {
+                    self.parse_address_register(token, &mut base, &mut index)?;
                 }
+                _ if is_symbol_name(&token.kind) && !is_negative && label.is_none() => {
+                    label = Some(token.text)
+                }
+                _ => return Err(unexpected(token)),
             }
+
+            let token = self.consume();
+            is_negative = match token.kind {
+                TokenKind::Symbol(Symbol::RBracket) => break,
+                TokenKind::Symbol(Symbol::Plus) => false,
+                TokenKind::Symbol(Symbol::Minus) => true,
+                _ => return Err(unexpected(token)),
+            };
+        }
+
+        let disp = match label {
+            Some(name) => Some(DispNode::Label {
+                name,
+                addend: disp.unwrap_or(0),
+            }),
+            None => disp.map(DispNode::Immediate),
+        };
+
+        Ok(OperandNode::Memory(MemoryNode {
+            base,
+            index,
+            disp,
+            size,
+            broadcast: None,
+        }))
+    }
+
+    // a register without a scale is the base, unless the base is already given
+    fn parse_address_register(
+        &mut self,
+        token: Token,
+        base: &mut Option<Register>,
+        index: &mut Option<IndexNode>,
+    ) -> Result<(), Error> {
+        let reg = match token.kind {
+            TokenKind::Register(ref reg) => reg.clone(),
             _ => return Err(unexpected(token)),
         };
 
-        self.expect(TokenKind::Symbol(Symbol::RBracket))?;
-        Ok(OperandNode::Memory(MemoryNode { base, disp }))
+        match self.parse_scale()? {
+            Some(scale) if index.is_none() => *index = Some(IndexNode { reg, scale }),
+            None if base.is_none() => *base = Some(reg),
+            None if index.is_none() => *index = Some(IndexNode { reg, scale: 1 }),
+            _ => return Err(unexpected(token)),
+        }
+        Ok(())
+    }
+
+    // the `*scale` that follows an index register
+    fn parse_scale(&mut self) -> Result<Option<u8>, Error> {
+        if self.peek().kind != TokenKind::Symbol(Symbol::Asterisk) {
+            return Ok(None);
+        }
+        self.consume();
+
+        let token = self.peek();
+        let scale = self.consume_integer()?;
+        match scale {
+            1 | 2 | 4 | 8 => Ok(Some(scale as u8)),
+            _ => Err(Error::new(token.pos, ErrorKind::InvalidScale { scale })),
+        }
     }
 
     fn parse_pseudop(&mut self, ident_token: Token) -> Result<InstructionNode, Error> {
         let op = find_pseudoop(ident_token)?;
         let args = match op {
-            PseudoOp::Tse => {
-                let mut args = Vec::new();
-                args.push(PseudoOpArg::Integer(self.consume_signed_integer()?));
-                self.expect(TokenKind::Symbol(Symbol::Comma))?;
-                args.push(PseudoOpArg::Integer(self.consume_signed_integer()?));
-                self.expect(TokenKind::Symbol(Symbol::Comma))?;
-                args.push(PseudoOpArg::Integer(self.consume_signed_integer()?));
-                args
-            }
+            PseudoOp::Tse => self.parse_tse_args()?,
             PseudoOp::IntelSyntax | PseudoOp::Global => {
                 vec![PseudoOpArg::String(self.consume_ident()?)]
             }
+            PseudoOp::PushSection => {
+                let mut args = vec![PseudoOpArg::String(self.consume_ident()?)];
+                if self.peek().kind == TokenKind::Symbol(Symbol::Comma) {
+                    self.consume();
+                    args.push(PseudoOpArg::Integer(self.consume_subsection()?));
+                }
+                args
+            }
+            PseudoOp::Subsection => vec![PseudoOpArg::Integer(self.consume_subsection()?)],
             PseudoOp::Zero => vec![PseudoOpArg::Integer(self.consume_integer()?)],
             PseudoOp::Ascii => vec![PseudoOpArg::String(self.consume_string()?)],
             _ => vec![],
@@ -158,6 +442,36 @@ impl Parser {
         Ok(InstructionNode::PseudoOp(op, args))
     }
 
+    // .tse [symbol,] offset, size, align
+    fn parse_tse_args(&mut self) -> Result<Vec<PseudoOpArg>, Error> {
+        let mut args = Vec::new();
+        if is_symbol_name(&self.peek().kind) {
+            args.push(PseudoOpArg::String(self.consume_ident()?));
+            self.expect(TokenKind::Symbol(Symbol::Comma))?;
+        }
+
+        let offset = self.consume_signed_integer()?;
+        self.expect(TokenKind::Symbol(Symbol::Comma))?;
+
+        let size_pos = self.peek().pos;
+        let size = self.consume_signed_integer()?;
+        if size.is_negative() {
+            return Err(Error::new(size_pos, ErrorKind::NegativeSize { size }));
+        }
+        self.expect(TokenKind::Symbol(Symbol::Comma))?;
+
+        let align_pos = self.peek().pos;
+        let align = self.consume_signed_integer()?;
+        if align <= 0 || align & (align - 1) != 0 {
+            return Err(Error::new(align_pos, ErrorKind::InvalidAlignment { align }));
+        }
+
+        args.push(PseudoOpArg::Integer(offset));
+        args.push(PseudoOpArg::Integer(size));
+        args.push(PseudoOpArg::Integer(align));
+        Ok(args)
+    }
+
     fn expect(&mut self, token: TokenKind) -> Result<Token, Error> {
         let next_token = self.consume();
         if next_token.kind == token {
@@ -173,7 +487,7 @@ impl Parser {
         }
     }
 
-    fn consume_integer(&mut self) -> Result<i32, Error> {
+    fn consume_integer(&mut self) -> Result<i64, Error> {
         let next_token = self.consume();
         match next_token.kind {
             TokenKind::Integer(value) => Ok(value),
@@ -184,7 +498,17 @@ impl Parser {
         }
     }
 
-    fn consume_signed_integer(&mut self) -> Result<i32, Error> {
+    // subsection numbers are unsigned 32-bit
+    fn consume_subsection(&mut self) -> Result<i64, Error> {
+        let pos = self.peek().pos;
+        let number = self.consume_signed_integer()?;
+        if number < 0 || number > i64::from(u32::MAX) {
+            return Err(Error::new(pos, ErrorKind::InvalidSubsection { number }));
+        }
+        Ok(number)
+    }
+
+    fn consume_signed_integer(&mut self) -> Result<i64, Error> {
         let next_token = self.consume();
         match next_token.kind {
             TokenKind::Symbol(Symbol::Minus) => Ok(-self.consume_integer()?),
@@ -213,7 +537,7 @@ impl Parser {
     fn consume_ident(&mut self) -> Result<String, Error> {
         let next_token = self.consume();
         match next_token.kind {
-            TokenKind::Ident(name) => Ok(name),
+            _ if is_symbol_name(&next_token.kind) => Ok(next_token.text),
             x => Err(Error::new(
                 next_token.pos,
                 ErrorKind::ExpectedIdent { actual: x },
@@ -235,31 +559,79 @@ impl Parser {
         self.tokens.get(self.pos).unwrap().clone()
     }
 
+    // the token after the next one, or EOF
+    fn peek_next(&self) -> Token {
+        let pos = (self.pos + 1).min(self.tokens.len() - 1);
+        self.tokens[pos].clone()
+    }
+
     fn is_eof(&mut self) -> bool {
         self.peek().kind == TokenKind::EOF
     }
 }
 
+fn is_shift_by_one(mnemonic: &Mnemonic, operands: &[OperandNode]) -> bool {
+    use Mnemonic::*;
+    matches!(mnemonic, Rcl | Rcr | Rol | Ror | Sar | Shl | Shr) && operands.len() == 1
+}
+
+// mnemonics and prefixes are only keywords where an instruction starts, and
+// name symbols as written anywhere else
+fn is_symbol_name(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Ident(_) | TokenKind::Mnemonic(_) | TokenKind::Prefix(_)
+    )
+}
+
 fn find_pseudoop(ident: Token) -> Result<PseudoOp, Error> {
     let name = match ident.kind {
         TokenKind::Ident(name) => name,
         _ => return Err(unexpected(ident)),
     };
 
-    match name.as_str() {
+    match name.to_ascii_lowercase().as_str() {
         ".global" => Ok(PseudoOp::Global),
         ".intel_syntax" => Ok(PseudoOp::IntelSyntax),
         ".data" => Ok(PseudoOp::Data),
         ".text" => Ok(PseudoOp::Text),
+        ".pushsection" => Ok(PseudoOp::PushSection),
+        ".popsection" => Ok(PseudoOp::PopSection),
+        ".previous" => Ok(PseudoOp::Previous),
+        ".subsection" => Ok(PseudoOp::Subsection),
         ".zero" => Ok(PseudoOp::Zero),
         ".ascii" => Ok(PseudoOp::Ascii),
         ".tse" => Ok(PseudoOp::Tse),
-        x => Err(Error::new(
-            ident.pos,
-            ErrorKind::UnknownPseudoOp {
-                name: x.to_string(),
-            },
-        )),
+        ".code16" => Ok(PseudoOp::Code16),
+        ".code32" => Ok(PseudoOp::Code32),
+        ".code64" => Ok(PseudoOp::Code64),
+        _ => Err(Error::new(ident.pos, ErrorKind::UnknownPseudoOp { name })),
+    }
+}
+
+// lists every number of operands the mnemonic accepts
+fn arities(mnemonic: &Mnemonic) -> &'static [usize] {
+    match mnemonic {
+        Mnemonic::IMul => &[1, 2, 3],
+        // the one-byte nop, or the multi-byte one
+        Mnemonic::Nop => &[0, 1],
+        Mnemonic::Ret | Mnemonic::Retf => &[0, 1],
+        // the string instruction, or the SSE move
+        Mnemonic::Movsd => &[0, 2],
+        // a shift or rotate by one, or by a count
+        Mnemonic::Rcl
+        | Mnemonic::Rcr
+        | Mnemonic::Rol
+        | Mnemonic::Ror
+        | Mnemonic::Sar
+        | Mnemonic::Shl
+        | Mnemonic::Shr => &[1, 2],
+        _ => match mnemonic.typ() {
+            mnemonic::Type::Nullary => &[0],
+            mnemonic::Type::Unary => &[1],
+            mnemonic::Type::Binary => &[2],
+            mnemonic::Type::Ternary => &[3],
+        },
     }
 }
 
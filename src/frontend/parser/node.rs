@@ -1,16 +1,22 @@
 use x86asm::instruction::{mnemonic::Mnemonic, operand::register::Register};
 
+use crate::{common::pos::Pos, frontend::lexer::token::Prefix};
+
 pub struct Program {
-    pub insts: Vec<InstructionNode>,
+    pub stmts: Vec<Statement>,
+}
+
+#[derive(Debug)]
+pub struct Statement {
+    pub node: InstructionNode,
+    pub pos: Pos,
 }
 
 #[derive(Debug)]
 pub enum InstructionNode {
     PseudoOp(PseudoOp, Vec<PseudoOpArg>),
     Label(String),
-    NullaryOp(Mnemonic),
-    UnaryOp(Mnemonic, OperandNode),
-    BinaryOp(Mnemonic, OperandNode, OperandNode),
+    Op(Option<Prefix>, Mnemonic, Vec<OperandNode>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -19,15 +25,22 @@ pub enum PseudoOp {
     IntelSyntax,
     Data,
     Text,
+    PushSection,
+    PopSection,
+    Previous,
+    Subsection,
     Zero,
     Ascii,
     Tse,
+    Code16,
+    Code32,
+    Code64,
 }
 
 #[derive(Debug)]
 pub enum PseudoOpArg {
     String(String),
-    Integer(i32),
+    Integer(i64),
 }
 
 impl PseudoOpArg {
@@ -38,7 +51,7 @@ impl PseudoOpArg {
         }
     }
 
-    pub fn as_integer(&self) -> &i32 {
+    pub fn as_integer(&self) -> &i64 {
         match self {
             PseudoOpArg::Integer(i) => i,
             _ => panic!(),
@@ -48,20 +61,78 @@ impl PseudoOpArg {
 
 #[derive(Debug, Clone)]
 pub enum OperandNode {
-    Immidiate(i32),
+    Immidiate(i64),
     Register(Register),
-    Label(String),
+    // a branch target, or an address used as an immediate
+    Label {
+        name: String,
+        addend: i64,
+    },
     Memory(MemoryNode),
+    // `segment:offset`, the target of a direct far jump or call
+    FarPointer {
+        segment: i64,
+        offset: DispNode,
+    },
+    // an AVX-512 destination followed by `{k}` and optionally `{z}`
+    Masked {
+        opr: Box<OperandNode>,
+        mask: Register,
+        is_zeroing: bool,
+    },
+    // `{rn-sae}` and the like, written after the last source
+    Rounding(Rounding),
+}
+
+impl OperandNode {
+    // the operand without its opmask
+    pub fn unmasked(&self) -> &OperandNode {
+        match self {
+            OperandNode::Masked { opr, .. } => opr,
+            opr => opr,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Rounding {
+    Nearest,
+    Down,
+    Up,
+    Zero,
 }
 
 #[derive(Debug, Clone)]
 pub struct MemoryNode {
-    pub base: Register,
+    pub base: Option<Register>,
+    pub index: Option<IndexNode>,
     pub disp: Option<DispNode>,
+    pub size: Option<OperandSize>,
+    // the number of elements of `{1toN}`
+    pub broadcast: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexNode {
+    pub reg: Register,
+    pub scale: u8,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum OperandSize {
+    Byte,
+    Word,
+    Dword,
+    Qword,
+    // a far pointer of a 16-bit segment and a 32-bit offset
+    Fword,
+    Xmmword,
+    Ymmword,
+    Zmmword,
 }
 
 #[derive(Debug, Clone)]
 pub enum DispNode {
-    Immediate(i32),
-    Label(String),
+    Immediate(i64),
+    Label { name: String, addend: i64 },
 }
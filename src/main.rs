@@ -1,22 +1,30 @@
-use rota::assembler;
+use rota::{assembler, backend::gen_code::Options};
 use std::env;
 
 extern crate rota;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if !matches!(args.len(), 3 | 4) {
+    if args.len() < 3 {
         show_usage();
     }
 
     let input_file = args[1].to_string();
     let output_file = args[2].to_string();
-    let output_raw = args.get(3).map_or(false, |arg| arg == "--raw");
+    let mut output_raw = false;
+    let mut options = Options::default();
+    for arg in &args[3..] {
+        match arg.as_str() {
+            "--raw" => output_raw = true,
+            "--long-branches" => options.force_long_branches = true,
+            _ => show_usage(),
+        }
+    }
 
     let err = if output_raw {
-        assembler::assemble_raw_to_file(input_file, output_file)
+        assembler::assemble_raw_to_file(input_file, output_file, options)
     } else {
-        assembler::assemble_to_file(input_file, output_file)
+        assembler::assemble_to_file(input_file, output_file, options)
     };
 
     if let Err(err) = err {
@@ -26,6 +34,6 @@ fn main() {
 }
 
 fn show_usage() {
-    println!("usage: rota <input_file> <output_file> [--raw]");
+    println!("usage: rota <input_file> <output_file> [--raw] [--long-branches]");
     std::process::exit(0);
 }
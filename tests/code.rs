@@ -1,7 +1,8 @@
 extern crate rota;
 
 use rota::{
-    backend::gen_code::{self, SectionName},
+    backend::gen_code::{self, Object, Options, RelaType, Section, SectionName},
+    common::error::Error,
     frontend::{
         lexer::{self, SourceFile},
         parser,
@@ -36,6 +37,119 @@ fn pop() {
     do_test("pop r8", "41 58");
 }
 
+#[test]
+fn push_pop_memory() {
+    do_test("push 1000\npush -1", "68 e8 03 00 00 6a ff");
+    do_test(
+        "push qword ptr [rax]\npush [rbx+8]\npop qword ptr [rdi]",
+        "ff 30 ff 73 08 8f 07",
+    );
+    do_test("push word ptr [rax]\npop [r9]", "66 ff 30 41 8f 01");
+    do_test(".code32\npush dword ptr [eax]", "ff 30");
+    do_test_error("push dword ptr [rax]", "unexpected Dword operand");
+
+    let text = text_section_with_relas("push qword ptr [rip+table]");
+    assert_eq!(bytes_to_str(&text.data), "ff 35 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 2);
+    assert_eq!(text.relas[0].addend, -4);
+}
+
+#[test]
+fn nop() {
+    do_test("nop", "90");
+    do_test("nop eax\nnop ax", "0f 1f c0 66 0f 1f c0");
+    do_test("nop [rax]\nnop qword ptr [rax]", "0f 1f 00 48 0f 1f 00");
+    do_test("nop dword ptr [rax+0]", "0f 1f 40 00");
+    do_test("nop dword ptr [rax+rax*1+0]", "0f 1f 44 00 00");
+    do_test("nop word ptr [rax+rax*1+0]", "66 0f 1f 44 00 00");
+    do_test("nop dword ptr [rax+1000]", "0f 1f 80 e8 03 00 00");
+    do_test("nop dword ptr [rax+rax*1+1000]", "0f 1f 84 00 e8 03 00 00");
+    do_test(
+        "nop word ptr [rax+rax*1+1000]",
+        "66 0f 1f 84 00 e8 03 00 00",
+    );
+    do_test_error("nop al", "unexpected Al");
+}
+
+#[test]
+fn trap_and_frame() {
+    do_test("int3\nud2\nendbr64", "cc 0f 0b f3 0f 1e fa");
+    do_test("enter 16,0\nleave", "c8 10 00 00 c9");
+    do_test_error("enter 65536,0", "integer out of range: 65536");
+    do_test_error("enter 0,256", "integer out of range: 256");
+}
+
+#[test]
+fn neg() {
+    do_test("neg eax", "f7 d8");
+    do_test("neg rax", "48 f7 d8");
+    do_test("neg r9", "49 f7 d9");
+    do_test("neg al", "f6 d8");
+    do_test("neg dword ptr [rax]", "f7 18");
+    do_test("neg qword ptr [r9+8]", "49 f7 59 08");
+}
+
+#[test]
+fn not() {
+    do_test("not eax", "f7 d0");
+    do_test("not rax", "48 f7 d0");
+    do_test("not r9", "49 f7 d1");
+    do_test("not al", "f6 d0");
+    do_test("not dword ptr [rax]", "f7 10");
+    do_test("not qword ptr [rax]", "48 f7 10");
+}
+
+#[test]
+fn inc() {
+    do_test("inc eax", "ff c0");
+    do_test("inc rax", "48 ff c0");
+    do_test("inc r9", "49 ff c1");
+    do_test("inc al", "fe c0");
+    do_test("inc dword ptr [rax]", "ff 00");
+    do_test("inc qword ptr [rbp-8]", "48 ff 45 f8");
+}
+
+#[test]
+fn dec() {
+    do_test("dec eax", "ff c8");
+    do_test("dec rax", "48 ff c8");
+    do_test("dec r9", "49 ff c9");
+    do_test("dec al", "fe c8");
+    do_test("dec dword ptr [rax]", "ff 08");
+    do_test("dec qword ptr [rax]", "48 ff 08");
+}
+
+#[test]
+fn mul() {
+    do_test("mul ebx", "f7 e3");
+    do_test("mul rbx", "48 f7 e3");
+    do_test("mul r9", "49 f7 e1");
+    do_test("mul bl", "f6 e3");
+    do_test("mul dword ptr [rax]", "f7 20");
+    do_test("mul qword ptr [rax]", "48 f7 20");
+}
+
+#[test]
+fn div() {
+    do_test("div ebx", "f7 f3");
+    do_test("div rbx", "48 f7 f3");
+    do_test("div r9", "49 f7 f1");
+    do_test("div bl", "f6 f3");
+    do_test("div dword ptr [rax]", "f7 30");
+    do_test("div qword ptr [rax+rcx*8]", "48 f7 34 c8");
+}
+
+#[test]
+fn sign_extend() {
+    do_test("cbw", "66 98");
+    do_test("cwde", "98");
+    do_test("cdqe", "48 98");
+    do_test("cwd", "66 99");
+    do_test("cdq", "99");
+    do_test("cqo", "48 99");
+}
+
 #[test]
 fn idiv() {
     do_test("idiv eax", "f7 f8");
@@ -45,12 +159,84 @@ fn idiv() {
 
 #[test]
 fn jmp() {
-    do_test("label: jmp label", "e9 fb ff ff ff"); // skip
+    do_test("label: jmp label", "eb fe"); // skip
 }
 
 #[test]
 fn je() {
-    do_test("label: je label", "0f 84 fa ff ff ff"); // skip
+    do_test("label: je label", "74 fe"); // skip
+}
+
+#[test]
+fn jcc() {
+    do_test("label: jo label", "70 fe"); // skip
+    do_test("label: jno label", "71 fe"); // skip
+    do_test("label: jb label", "72 fe"); // skip
+    do_test("label: jc label", "72 fe"); // skip
+    do_test("label: jnae label", "72 fe"); // skip
+    do_test("label: jae label", "73 fe"); // skip
+    do_test("label: jnb label", "73 fe"); // skip
+    do_test("label: jnc label", "73 fe"); // skip
+    do_test("label: jz label", "74 fe"); // skip
+    do_test("label: jne label", "75 fe"); // skip
+    do_test("label: jnz label", "75 fe"); // skip
+    do_test("label: jbe label", "76 fe"); // skip
+    do_test("label: jna label", "76 fe"); // skip
+    do_test("label: ja label", "77 fe"); // skip
+    do_test("label: jnbe label", "77 fe"); // skip
+    do_test("label: js label", "78 fe"); // skip
+    do_test("label: jns label", "79 fe"); // skip
+    do_test("label: jp label", "7a fe"); // skip
+    do_test("label: jpe label", "7a fe"); // skip
+    do_test("label: jnp label", "7b fe"); // skip
+    do_test("label: jpo label", "7b fe"); // skip
+    do_test("label: jl label", "7c fe"); // skip
+    do_test("label: jnge label", "7c fe"); // skip
+    do_test("label: jge label", "7d fe"); // skip
+    do_test("label: jnl label", "7d fe"); // skip
+    do_test("label: jle label", "7e fe"); // skip
+    do_test("label: jng label", "7e fe"); // skip
+    do_test("label: jg label", "7f fe"); // skip
+    do_test("label: jnle label", "7f fe"); // skip
+    do_test("label: jrcxz label", "e3 fe"); // skip
+    do_test_error("jrcxz foo", "branch target out of range: 'foo'");
+}
+
+#[test]
+fn relax_branch() {
+    let zeros = |n| "00 ".repeat(n);
+    do_test(
+        "jmp end\n.zero 127\nend: ret",
+        &format!("eb 7f {}c3", zeros(127)),
+    );
+    do_test(
+        "jmp end\n.zero 128\nend: ret",
+        &format!("e9 80 00 00 00 {}c3", zeros(128)),
+    );
+    do_test(
+        "begin: .zero 126\nje begin",
+        &format!("{}74 80", zeros(126)),
+    );
+    do_test(
+        "begin: .zero 127\nje begin",
+        &format!("{}0f 84 7b ff ff ff", zeros(127)),
+    );
+    // growing the second jump pushes `end` out of range of the first one
+    do_test(
+        "jmp end\n.zero 124\njmp far\nend: ret\n.zero 128\nfar: ret",
+        &format!(
+            "e9 81 00 00 00 {}e9 81 00 00 00 c3 {}c3",
+            zeros(124),
+            zeros(128)
+        ),
+    );
+    do_test("jmp main\n.global main\nmain: ret", "e9 00 00 00 00 c3");
+
+    let options = Options {
+        force_long_branches: true,
+    };
+    let obj = generate_with("label: jmp label\njrcxz label", options);
+    assert_eq!(text_section(obj), "e9 fb ff ff ff e3 f9");
 }
 
 #[test]
@@ -58,6 +244,74 @@ fn call() {
     do_test("label: call label", "e8 fb ff ff ff");
 }
 
+#[test]
+fn indirect_branch() {
+    do_test("call rax", "ff d0");
+    do_test("jmp r9", "41 ff e1");
+    do_test("call [rax]", "ff 10");
+    do_test("jmp qword ptr [rax+8]", "ff 60 08");
+    do_test_error("call eax", "unexpected Eax");
+    do_test_error("jmp dword ptr [rax]", "unexpected Dword operand");
+
+    let text = text_section_with_relas("jmp qword ptr [rip+table]");
+    assert_eq!(bytes_to_str(&text.data), "ff 25 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 2);
+
+    let text = text_section_with_relas("jmp [rax*8+table]");
+    assert_eq!(bytes_to_str(&text.data), "ff 24 c5 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32S));
+    assert_eq!(text.relas[0].offset, 3);
+    assert_eq!(text.relas[0].addend, 0);
+}
+
+#[test]
+fn scaled_index() {
+    do_test("mov rax,[rbx+rcx*4+8]", "48 8b 44 8b 08");
+    do_test("mov rax,[rbx+rcx]", "48 8b 04 0b");
+    do_test("mov rax,[rax*8]", "48 8b 04 c5 00 00 00 00");
+    do_test("lea rax,[rcx*2-8]", "48 8d 04 4d f8 ff ff ff");
+    do_test_error("mov rax,[rax*3]", "scale must be 1, 2, 4 or 8: 3");
+}
+
+#[test]
+fn absolute_address() {
+    do_test("mov rax,[1000]", "48 8b 04 25 e8 03 00 00");
+    do_test("inc dword ptr [-8]", "ff 04 25 f8 ff ff ff");
+    do_test(".code32\nmov eax,[1000]", "8b 04 25 e8 03 00 00");
+
+    let text = text_section_with_relas("lgdt [gdt_ptr]");
+    assert_eq!(bytes_to_str(&text.data), "0f 01 14 25 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32S));
+    assert_eq!(text.relas[0].offset, 4);
+
+    let text = text_section_with_relas(".code32\nlgdt [gdt_ptr+2]");
+    assert_eq!(bytes_to_str(&text.data), "0f 01 14 25 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32));
+    assert_eq!(text.relas[0].offset, 4);
+    assert_eq!(text.relas[0].addend, 2);
+
+    let text = text_section_with_relas("mov rax,[table+rcx*8]");
+    assert_eq!(bytes_to_str(&text.data), "48 8b 04 cd 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32S));
+    assert_eq!(text.relas[0].offset, 4);
+}
+
+#[test]
+fn address_register() {
+    do_test("mov rax,[rsp+rax*2]", "48 8b 04 44");
+    do_test("mov rax,[r12+r13*2]", "4b 8b 04 6c");
+    do_test_error("mov rax,[eax]", "unexpected Eax");
+    do_test_error("mov rax,[rbx+ecx]", "unexpected Ecx");
+    do_test_error("mov rax,[xmm1]", "unexpected Xmm1");
+    do_test_error("mov rax,[k1]", "unexpected K1");
+    do_test_error("mov rax,[cr3]", "unexpected Cr3");
+    do_test_error("mov rax,[es]", "unexpected Es");
+    do_test_error("mov rax,[rax+rsp*2]", "unexpected Rsp");
+    do_test_error("mov rax,[rax+rip]", "unexpected Rip");
+    do_test_error(".code32\nmov eax,[eax+esp*2]", "unexpected Esp");
+}
+
 #[test]
 fn sete() {
     do_test("sete al", "0f 94 c0");
@@ -94,6 +348,33 @@ fn setge() {
     do_test("setge r9b", "41 0f 9d c1");
 }
 
+#[test]
+fn setcc() {
+    do_test("seto al", "0f 90 c0");
+    do_test("setno al", "0f 91 c0");
+    do_test("setb al", "0f 92 c0");
+    do_test("setc al", "0f 92 c0");
+    do_test("setae al", "0f 93 c0");
+    do_test("setnc al", "0f 93 c0");
+    do_test("setz al", "0f 94 c0");
+    do_test("setnz al", "0f 95 c0");
+    do_test("setbe al", "0f 96 c0");
+    do_test("setna al", "0f 96 c0");
+    do_test("seta al", "0f 97 c0");
+    do_test("setnbe al", "0f 97 c0");
+    do_test("sets al", "0f 98 c0");
+    do_test("setns al", "0f 99 c0");
+    do_test("setp al", "0f 9a c0");
+    do_test("setpe al", "0f 9a c0");
+    do_test("setnp al", "0f 9b c0");
+    do_test("setpo al", "0f 9b c0");
+    do_test("setnge al", "0f 9c c0");
+    do_test("setnl al", "0f 9d c0");
+    do_test("setng al", "0f 9e c0");
+    do_test("setnle al", "0f 9f c0");
+    do_test("seta r9b", "41 0f 97 c1");
+}
+
 #[test]
 fn add() {
     do_test("add eax,1", "83 c0 01");
@@ -159,6 +440,57 @@ fn imul() {
     do_test("imul rax,[rax+8]", "48 0f af 40 08");
     do_test("imul rax,[r9-8]", "49 0f af 41 f8");
     do_test("imul rax,[r9-129]", "49 0f af 81 7f ff ff ff");
+    do_test("imul rbx", "48 f7 eb");
+    do_test("imul r9", "49 f7 e9");
+    do_test("imul rax,rbx,12", "48 6b c3 0c");
+    do_test("imul eax,ebx,12", "6b c3 0c");
+    do_test("imul r9,rax,12", "4c 6b c8 0c");
+    do_test("imul rax,[rbx],1000", "48 69 03 e8 03 00 00");
+    do_test_error("imul rax,rbx,1,2", "IMul does not take 4 operands");
+}
+
+#[test]
+fn adc() {
+    do_test("adc eax,1", "83 d0 01");
+    do_test("adc rax,1", "48 83 d0 01");
+    do_test("adc r9,1", "49 83 d1 01");
+    do_test("adc rax,rbx", "48 11 d8");
+    do_test("adc rax,[rbx]", "48 13 03");
+    do_test("adc [rbx],rax", "48 11 03");
+    do_test("adc rax,[r9+8]", "49 13 41 08");
+}
+
+#[test]
+fn sbb() {
+    do_test("sbb eax,1", "83 d8 01");
+    do_test("sbb rax,1", "48 83 d8 01");
+    do_test("sbb r9,1", "49 83 d9 01");
+    do_test("sbb rax,rbx", "48 19 d8");
+    do_test("sbb rax,[rbx]", "48 1b 03");
+    do_test("sbb [rbx],rax", "48 19 03");
+    do_test("sbb rax,[r9+8]", "49 1b 41 08");
+}
+
+#[test]
+fn test() {
+    do_test("test eax,1", "f7 c0 01 00 00 00");
+    do_test("test rax,1", "48 f7 c0 01 00 00 00");
+    do_test("test r9,1", "49 f7 c1 01 00 00 00");
+    do_test("test eax,eax", "85 c0");
+    do_test("test rax,rbx", "48 85 d8");
+    do_test("test r9,rax", "49 85 c1");
+    do_test("test [rax],rbx", "48 85 18");
+    do_test("test [r9-8],rax", "49 85 41 f8");
+}
+
+#[test]
+fn xchg() {
+    do_test("xchg eax,ebx", "87 d8");
+    do_test("xchg rax,rbx", "48 87 d8");
+    do_test("xchg rbx,rcx", "48 87 cb");
+    do_test("xchg r9,rax", "49 87 c1");
+    do_test("xchg [rax],rbx", "48 87 18");
+    do_test("xchg [r9+8],rax", "49 87 41 08");
 }
 
 #[test]
@@ -209,6 +541,77 @@ fn mov() {
     do_test("mov [r9-129],rax", "49 89 81 7f ff ff ff");
 }
 
+#[test]
+fn number_literal() {
+    do_test("push 0x10\npush 0b101", "6a 10 6a 05");
+    do_test("push 0XfF", "68 ff 00 00 00");
+    do_test("mov rax,0xffffffffffffffff", "48 c7 c0 ff ff ff ff");
+    do_test("movabs rax,0x123456789", "48 b8 89 67 45 23 01 00 00 00");
+    do_test_error("push 0x", "invalid number: '0x'");
+    do_test_error("push 0b2", "invalid number: '0b'");
+    do_test_error(
+        "push 0x10000000000000000",
+        "invalid number: '0x10000000000000000'",
+    );
+}
+
+#[test]
+fn movabs() {
+    do_test("movabs rax,1", "48 c7 c0 01 00 00 00");
+    do_test("movabs rax,-1", "48 c7 c0 ff ff ff ff");
+    do_test("movabs rax,4294967295", "c7 c0 ff ff ff ff");
+    do_test("movabs r9,2147483648", "41 c7 c1 00 00 00 80");
+    do_test("movabs rax,4294967296", "48 b8 00 00 00 00 01 00 00 00");
+    do_test("mov r9,-4294967296", "49 b9 00 00 00 00 ff ff ff ff");
+    do_test_error("mov eax,4294967296", "integer out of range: 4294967296");
+
+    let text = text_section_with_relas("movabs rax,table\nmov rbx,table");
+    assert_eq!(
+        bytes_to_str(&text.data),
+        "48 b8 00 00 00 00 00 00 00 00 48 bb 00 00 00 00 00 00 00 00"
+    );
+    assert_eq!(text.relas.len(), 2);
+    assert_eq!(text.relas[0].name, "table");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs64));
+    assert_eq!(text.relas[0].offset, 2);
+    assert_eq!(text.relas[1].offset, 12);
+
+    // local labels are relocated against their section
+    let text = text_section_with_relas(".data\nhlt\ntable:\n.text\nmovabs rax,table");
+    assert_eq!(text.relas[0].name, ".data");
+    assert_eq!(text.relas[0].addend, 1);
+}
+
+#[test]
+fn label_immediate() {
+    let text = text_section_with_relas("mov eax,msg\nmsg:");
+    assert_eq!(bytes_to_str(&text.data), "c7 c0 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32));
+    assert_eq!(text.relas[0].name, ".text");
+    assert_eq!(text.relas[0].offset, 2);
+    assert_eq!(text.relas[0].addend, 6);
+
+    let text = text_section_with_relas("push handler");
+    assert_eq!(bytes_to_str(&text.data), "68 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32S));
+    assert_eq!(text.relas[0].name, "handler");
+    assert_eq!(text.relas[0].offset, 1);
+    assert_eq!(text.relas[0].addend, 0);
+
+    let text = text_section_with_relas("mov eax,offset msg+8");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32));
+    assert_eq!(text.relas[0].addend, 8);
+
+    let text = text_section_with_relas("add rax,offset table-4");
+    assert_eq!(bytes_to_str(&text.data), "48 81 c0 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32S));
+    assert_eq!(text.relas[0].offset, 3);
+    assert_eq!(text.relas[0].addend, -4);
+
+    do_test("jmp foo+2\nfoo:", "eb 02");
+    do_test_error("mov byte ptr [rax],msg", "unexpected Byte operand");
+}
+
 #[test]
 fn movsx() {
     do_test("movsx rax, byte ptr [rax]", "48 0f be 00");
@@ -218,6 +621,132 @@ fn movsx() {
     do_test("movsx rax, byte ptr [rax+8]", "48 0f be 40 08");
     do_test("movsx rax, byte ptr [r9-8]", "49 0f be 41 f8");
     do_test("movsx rax, byte ptr [r9-129]", "49 0f be 81 7f ff ff ff");
+    do_test("movsx eax, byte ptr [rdi]", "0f be 07");
+    do_test("movsx rax, word ptr [rdi]", "48 0f bf 07");
+    do_test("movsx eax, word ptr [r9+8]", "41 0f bf 41 08");
+    do_test("movsx rax, dword ptr [rdi]", "48 63 07");
+    do_test("movsx eax, cl", "0f be c1");
+    do_test("movsx rax, r9b", "49 0f be c1");
+    do_test("movsx rax, ecx", "48 63 c1");
+    do_test_error("movsx rax, [rdi]", "operand size is not specified");
+    do_test_error("movsx rax, qword ptr [rdi]", "unexpected Qword operand");
+    do_test_error("movsx rax, rcx", "unexpected Rcx");
+}
+
+#[test]
+fn movzx() {
+    do_test("movzx eax, byte ptr [rdi]", "0f b6 07");
+    do_test("movzx rax, byte ptr [rax]", "48 0f b6 00");
+    do_test("movzx rax, byte ptr [r9-8]", "49 0f b6 41 f8");
+    do_test("movzx eax, word ptr [rdi]", "0f b7 07");
+    do_test("movzx rax, word ptr [r12]", "49 0f b7 04 24");
+    do_test("movzx eax, cl", "0f b6 c1");
+    do_test("movzx rax, r9b", "49 0f b6 c1");
+    do_test_error("movzx rax, [rdi]", "operand size is not specified");
+    do_test_error("movzx rax, dword ptr [rdi]", "unexpected Dword operand");
+    do_test_error("movzx rax, ecx", "unexpected Ecx");
+}
+
+#[test]
+fn movsxd() {
+    do_test("movsxd rax, ecx", "48 63 c1");
+    do_test("movsxd rax, dword ptr [rdi]", "48 63 07");
+    do_test("movsxd r9, dword ptr [rax+8]", "4c 63 48 08");
+    do_test_error("movsxd rax, byte ptr [rdi]", "unexpected Byte operand");
+    do_test_error("movsxd rax, rcx", "unexpected Rcx");
+}
+
+#[test]
+fn cmovcc() {
+    do_test("cmovo rax,rbx", "48 0f 40 c3");
+    do_test("cmovno rax,rbx", "48 0f 41 c3");
+    do_test("cmovb rax,rbx", "48 0f 42 c3");
+    do_test("cmovc rax,rbx", "48 0f 42 c3");
+    do_test("cmovae rax,rbx", "48 0f 43 c3");
+    do_test("cmovnc rax,rbx", "48 0f 43 c3");
+    do_test("cmove rax,rbx", "48 0f 44 c3");
+    do_test("cmovz rax,rbx", "48 0f 44 c3");
+    do_test("cmovne rax,rbx", "48 0f 45 c3");
+    do_test("cmovnz rax,rbx", "48 0f 45 c3");
+    do_test("cmovbe rax,rbx", "48 0f 46 c3");
+    do_test("cmova rax,rbx", "48 0f 47 c3");
+    do_test("cmovs rax,rbx", "48 0f 48 c3");
+    do_test("cmovns rax,rbx", "48 0f 49 c3");
+    do_test("cmovp rax,rbx", "48 0f 4a c3");
+    do_test("cmovnp rax,rbx", "48 0f 4b c3");
+    do_test("cmovl rax,rbx", "48 0f 4c c3");
+    do_test("cmovge rax,rbx", "48 0f 4d c3");
+    do_test("cmovle rax,rbx", "48 0f 4e c3");
+    do_test("cmovg rax,rbx", "48 0f 4f c3");
+    do_test("cmove eax,ebx", "0f 44 c3");
+    do_test("cmove r9,rax", "4c 0f 44 c8");
+    do_test("cmove rax,[rbx]", "48 0f 44 03");
+    do_test("cmovl rax,[rbx+8]", "48 0f 4c 43 08");
+    do_test("cmovg eax,[r9]", "41 0f 4f 01");
+    do_test_error("cmove al,bl", "unexpected Al");
+    do_test_error("cmove eax,r9b", "unexpected R9b");
+    do_test_error("cmove eax,byte ptr [rax]", "unexpected Byte operand");
+}
+
+#[test]
+fn shift() {
+    do_test("rol rax,1", "48 d1 c0");
+    do_test("rol rax,4", "48 c1 c0 04");
+    do_test("rol rax,cl", "48 d3 c0");
+    do_test("ror rax,1", "48 d1 c8");
+    do_test("ror rax,4", "48 c1 c8 04");
+    do_test("ror rax,cl", "48 d3 c8");
+    do_test("rcl rax,1", "48 d1 d0");
+    do_test("rcl rax,4", "48 c1 d0 04");
+    do_test("rcl rax,cl", "48 d3 d0");
+    do_test("rcr rax,1", "48 d1 d8");
+    do_test("rcr rax,4", "48 c1 d8 04");
+    do_test("rcr rax,cl", "48 d3 d8");
+    do_test("shl rax,1", "48 d1 e0");
+    do_test("shl rax,4", "48 c1 e0 04");
+    do_test("shl rax,cl", "48 d3 e0");
+    do_test("sal rax,1", "48 d1 e0");
+    do_test("sal rax,4", "48 c1 e0 04");
+    do_test("sal rax,cl", "48 d3 e0");
+    do_test("shr rax,1", "48 d1 e8");
+    do_test("shr rax,4", "48 c1 e8 04");
+    do_test("shr rax,cl", "48 d3 e8");
+    do_test("sar rax,1", "48 d1 f8");
+    do_test("sar rax,4", "48 c1 f8 04");
+    do_test("sar rax,cl", "48 d3 f8");
+    do_test("shl eax,1", "d1 e0");
+    do_test("shl al,1", "d0 e0");
+    do_test("shl al,cl", "d2 e0");
+    do_test("shl r9,3", "49 c1 e1 03");
+    do_test("shr r9b,cl", "41 d2 e9");
+    do_test(
+        "shl rax\nsal rax\nshr eax\nsar r9",
+        "48 d1 e0 48 d1 e0 d1 e8 49 d1 f9",
+    );
+    do_test(
+        "rol rax\nror al\nrcl ebx\nrcr cx",
+        "48 d1 c0 d0 c8 d1 d3 66 d1 d9",
+    );
+    do_test("shl dword ptr [rax]", "d1 20");
+    do_test_error("shl rax,dl", "unexpected Dl");
+    do_test_error("sar eax,rcx", "unexpected Rcx");
+    do_test_error("shl", "Shl does not take 0 operands");
+}
+
+#[test]
+fn shld() {
+    do_test("shld rax,rbx,4", "48 0f a4 d8 04");
+    do_test("shld rax,rbx,cl", "48 0f a5 d8");
+    do_test("shld eax,ebx,1", "0f a4 d8 01");
+    do_test("shld r9,rax,4", "49 0f a4 c1 04");
+    do_test_error("shld rax,rbx,dl", "unexpected Dl");
+}
+
+#[test]
+fn shrd() {
+    do_test("shrd rax,rbx,4", "48 0f ac d8 04");
+    do_test("shrd rax,rbx,cl", "48 0f ad d8");
+    do_test("shrd eax,ebx,1", "0f ac d8 01");
 }
 
 #[test]
@@ -292,6 +821,32 @@ fn cmp() {
     do_test("cmp [r9-129],rax", "49 39 81 7f ff ff ff");
 }
 
+#[test]
+fn register_size() {
+    do_test("mov r8d,r9d", "45 89 c8");
+    do_test("mov r15d,1", "41 c7 c7 01 00 00 00");
+    do_test("add r10d,1", "41 83 c2 01");
+    do_test("mov ax,bx", "66 89 d8");
+    do_test("mov r8w,1", "66 41 c7 c0 01 00");
+    do_test("add ax,1000", "66 81 c0 e8 03");
+    do_test("add cx,1", "66 83 c1 01");
+    do_test("mov ax,[rax]", "66 8b 00");
+    do_test("movzx eax,ax", "0f b7 c0");
+    do_test_error("add ax,65536", "integer out of range: 65536");
+}
+
+#[test]
+fn high_byte_register() {
+    do_test("mov ah,bh", "88 fc");
+    do_test("mov ah,1", "c6 c4 01");
+    do_test("mov byte ptr [rax],ah", "88 20");
+    do_test("movzx eax,dh", "0f b6 c6");
+    do_test_error("mov ah,r8b", "unexpected Ah");
+    do_test_error("mov ch,sil", "unexpected Ch");
+    do_test_error("movzx rax,ah", "unexpected Ah");
+    do_test_error("mov byte ptr [r9],dh", "unexpected Dh");
+}
+
 #[test]
 fn lea() {
     do_test("lea rax,[rax]", "48 8d 00");
@@ -303,6 +858,513 @@ fn lea() {
     do_test("lea rax,[r9-129]", "49 8d 81 7f ff ff ff");
 }
 
+#[test]
+fn immediate() {
+    do_test("ret 8", "c2 08 00");
+    do_test("add rax,-100000", "48 81 c0 60 79 fe ff");
+    do_test("add rax,127", "48 83 c0 7f");
+    do_test("add rax,128", "48 81 c0 80 00 00 00");
+    do_test("add al,200", "80 c0 c8");
+    do_test("shl rax,255", "48 c1 e0 ff");
+    do_test("mov eax,4294967295", "c7 c0 ff ff ff ff");
+    do_test("mov rax,-1", "48 c7 c0 ff ff ff ff");
+    do_test("mov rax,4294967296", "48 b8 00 00 00 00 01 00 00 00");
+    do_test("mov qword ptr [rax],1", "48 c7 00 01 00 00 00");
+    do_test_error("add rax,4294967296", "integer out of range: 4294967296");
+    do_test_error("add al,256", "integer out of range: 256");
+    do_test_error("shl rax,256", "integer out of range: 256");
+    do_test_error("ret 65536", "integer out of range: 65536");
+    do_test_error(
+        "mov qword ptr [rax],4294967296",
+        "integer out of range: 4294967296",
+    );
+    do_test_error(
+        "mov rax,[rax+4294967296]",
+        "integer out of range: 4294967296",
+    );
+}
+
+#[test]
+fn sse_move() {
+    do_test("movss xmm0,xmm1", "f3 0f 10 c1");
+    do_test("movsd xmm0,qword ptr [rax]", "f2 0f 10 00");
+    do_test("movsd qword ptr [rsp+8],xmm1", "f2 0f 11 4c 24 08");
+    do_test("movaps xmm8,xmm1", "44 0f 28 c1");
+    do_test("movups xmm0,[r9]", "41 0f 10 01");
+    do_test("movaps xmm0,xmmword ptr [rax]", "0f 28 00");
+    do_test("movups xmmword ptr [rsp+16],xmm1", "0f 11 4c 24 10");
+    do_test("pxor xmm0,xmmword ptr [rax]", "66 0f ef 00");
+    do_test("movq xmm0,rax", "66 48 0f 6e c0");
+    do_test("movq rax,xmm0", "66 48 0f 7e c0");
+    do_test("movq xmm1,xmm2", "f3 0f 7e ca");
+    do_test("movd xmm0,eax", "66 0f 6e c0");
+    do_test("movd eax,xmm15", "66 44 0f 7e f8");
+    do_test_error("movsd xmm0,dword ptr [rax]", "invalid operands for Movsd");
+    do_test_error("movd xmm0,rax", "invalid operands for Movd");
+    do_test_error("movsd xmm0,xmmword ptr [rax]", "invalid operands for Movsd");
+    do_test_error("movaps xmm0,qword ptr [rax]", "invalid operands for Movaps");
+    do_test_error("add xmmword ptr [rax],1", "unexpected Xmmword operand");
+
+    let text = text_section_with_relas("movsd xmm0,qword ptr [rip+one]");
+    assert_eq!(bytes_to_str(&text.data), "f2 0f 10 05 00 00 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 4);
+    assert_eq!(text.relas[0].addend, -4);
+
+    // local constants are relocated against their section
+    let text =
+        text_section_with_relas(".data\n.zero 8\n.LC0:\n.zero 8\n.text\nmovsd xmm0,[rip+.LC0]");
+    assert_eq!(bytes_to_str(&text.data), "f2 0f 10 05 00 00 00 00");
+    assert_eq!(text.relas[0].name, ".data");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].addend, 4);
+}
+
+#[test]
+fn sse_arith() {
+    do_test("addsd xmm0,xmm1", "f2 0f 58 c1");
+    do_test("addss xmm0,dword ptr [rax]", "f3 0f 58 00");
+    do_test("subsd xmm0,xmm1", "f2 0f 5c c1");
+    do_test("mulsd xmm0,[rbp-8]", "f2 0f 59 45 f8");
+    do_test("divsd xmm9,xmm1", "f2 44 0f 5e c9");
+    do_test("sqrtsd xmm0,xmm1", "f2 0f 51 c1");
+    do_test("ucomisd xmm0,xmm1", "66 0f 2e c1");
+    do_test("comisd xmm0,xmm1", "66 0f 2f c1");
+    do_test("pxor xmm0,xmm0", "66 0f ef c0");
+    do_test("xorps xmm0,xmm0", "0f 57 c0");
+    do_test("andpd xmm1,xmm2", "66 0f 54 ca");
+    do_test_error("addsd xmm0,rax", "invalid operands for Addsd");
+}
+
+#[test]
+fn sse_convert() {
+    do_test("cvtsi2sd xmm0,eax", "f2 0f 2a c0");
+    do_test("cvtsi2sd xmm0,rax", "f2 48 0f 2a c0");
+    do_test("cvtsi2sd xmm0,qword ptr [rax]", "f2 48 0f 2a 00");
+    do_test("cvttsd2si eax,xmm1", "f2 0f 2c c1");
+    do_test("cvttsd2si rax,xmm0", "f2 48 0f 2c c0");
+    do_test("cvtss2sd xmm0,xmm1", "f3 0f 5a c1");
+}
+
+#[test]
+fn avx() {
+    do_test("vzeroupper", "c5 f8 77");
+    do_test("vmovdqu ymm0,[rdi]", "c5 fe 6f 07");
+    do_test("vmovdqu [rdi],ymm1", "c5 fe 7f 0f");
+    do_test("vmovdqu xmm0,xmm1", "c5 fa 6f c1");
+    do_test("vpaddd ymm0,ymm1,ymm2", "c5 f5 fe c2");
+    do_test("vpcmpeqb ymm1,ymm0,[rdi]", "c5 fd 74 0f");
+    do_test("vpmovmskb eax,ymm1", "c5 fd d7 c1");
+    do_test("vpxor xmm8,xmm8,xmm1", "c5 39 ef c1");
+    do_test("vmovdqu ymm0,ymmword ptr [rdi]", "c5 fe 6f 07");
+    do_test("vmovdqu ymmword ptr [rdi],ymm1", "c5 fe 7f 0f");
+    do_test("vmovdqu xmmword ptr [rdi],xmm1", "c5 fa 7f 0f");
+    do_test("vpcmpeqb ymm1,ymm0,ymmword ptr [rdi]", "c5 fd 74 0f");
+    do_test_error("vpaddd ymm0,xmm1,ymm2", "invalid operands for Vpaddd");
+    do_test_error(
+        "vmovdqu ymm0,xmmword ptr [rdi]",
+        "invalid operands for Vmovdqu",
+    );
+    do_test_error(
+        "vpaddd xmm0,xmm1,ymmword ptr [rax]",
+        "invalid operands for Vpaddd",
+    );
+    do_test_error(
+        "movaps xmm0,ymmword ptr [rax]",
+        "invalid operands for Movaps",
+    );
+    do_test_error("add ymmword ptr [rax],1", "unexpected Ymmword operand");
+}
+
+#[test]
+fn avx_three_byte_vex() {
+    do_test("vfmadd231pd ymm0,ymm1,ymm2", "c4 e2 f5 b8 c2");
+    do_test("vbroadcastss ymm0,dword ptr [rax]", "c4 e2 7d 18 00");
+    do_test("vbroadcastss xmm0,xmm1", "c4 e2 79 18 c1");
+    do_test("vpaddd ymm8,ymm9,ymm10", "c4 41 35 fe c2");
+}
+
+#[test]
+fn avx512() {
+    do_test("vaddps zmm0,zmm1,zmm2", "62 f1 74 48 58 c2");
+    do_test("vaddps ymm0,ymm1,ymm2", "c5 f4 58 c2");
+    do_test("vaddps xmm16,xmm1,xmm2", "62 e1 74 08 58 c2");
+    do_test("vpaddd zmm16,zmm17,zmm31", "62 81 75 40 fe c7");
+    do_test("vaddpd zmm0{k1}{z},zmm1,zmm2", "62 f1 f5 c9 58 c2");
+    do_test(
+        "vaddps zmm0,zmm1,dword ptr [rax]{1to16}",
+        "62 f1 74 58 58 00",
+    );
+    do_test("vaddps zmm0,zmm1,zmm2,{rn-sae}", "62 f1 74 18 58 c2");
+    do_test("vaddps zmm0,zmm1,zmm2,{rz-sae}", "62 f1 74 78 58 c2");
+    do_test(
+        "vaddpd zmm0,zmm1,qword ptr [rax]{1to8}",
+        "62 f1 f5 58 58 00",
+    );
+    do_test("vmovups [rax]{k1},zmm0", "62 f1 7c 49 11 00");
+    do_test("vmovups zmm0,[rax+64]", "62 f1 7c 48 10 40 01");
+    do_test("vmovups zmm0,zmmword ptr [rax+64]", "62 f1 7c 48 10 40 01");
+    do_test("vmovups zmmword ptr [rax]{k1},zmm0", "62 f1 7c 49 11 00");
+    do_test("vmovups zmm0,[rax+8]", "62 f1 7c 48 10 80 08 00 00 00");
+    do_test("vpcmpeqd k1,zmm0,zmm1", "62 f1 7d 48 76 c9");
+    do_test_error("vaddps zmm0,zmm1,ymm2", "invalid operands for Vaddps");
+    do_test_error(
+        "vaddps zmm0,zmm1,ymmword ptr [rax]",
+        "invalid operands for Vaddps",
+    );
+    do_test_error(
+        "vaddps zmm0,zmm1,zmmword ptr [rax]{1to16}",
+        "invalid operands for Vaddps",
+    );
+    do_test_error(
+        "vmovdqu ymm0,zmmword ptr [rax]",
+        "invalid operands for Vmovdqu",
+    );
+    do_test_error("add zmmword ptr [rax],1", "unexpected Zmmword operand");
+    do_test_error("addsd xmm0{k1},xmm1", "invalid operands for Addsd");
+    do_test_error("vaddps zmm0{k0},zmm1,zmm2", "unexpected K0");
+    do_test_error(
+        "vaddpd zmm0,zmm1,dword ptr [rax]{1to8}",
+        "invalid operands for Vaddpd",
+    );
+    do_test_error(
+        "vaddps zmm0,zmm1,qword ptr [rax]{1to16}",
+        "invalid operands for Vaddps",
+    );
+    do_test_error("vmovups [rax]{k1}{z},zmm0", "invalid operands for Vmovups");
+    do_test_error(
+        "vpaddd zmm0,zmm1,zmm2,{rn-sae}",
+        "invalid operands for Vpaddd",
+    );
+}
+
+#[test]
+fn string() {
+    do_test("movsb", "a4");
+    do_test("movsw", "66 a5");
+    do_test("movsd", "a5");
+    do_test("movsq", "48 a5");
+    do_test("stosb\nlodsd\nscasw\ncmpsq", "aa ad 66 af 48 a7");
+    do_test("rep movsb", "f3 a4");
+    do_test("rep stosq", "f3 48 ab");
+    do_test("rep movsb\nlabel: jmp label", "f3 a4 eb fe");
+    do_test("repe cmpsb\nrepz scasd", "f3 a6 f3 af");
+    do_test("repne scasb\nrepnz cmpsw", "f2 ae f2 66 a7");
+    do_test("cld\nstd", "fc fd");
+    do_test("movsd xmm0,xmm1", "f2 0f 10 c1");
+    do_test_error("movsd xmm0", "Movsd does not take 1 operands");
+    do_test_error("rep ret", "Rep cannot be used with Ret");
+    do_test_error("repne movsb", "Repne cannot be used with Movsb");
+    do_test_error("rep movsd xmm0,xmm1", "Rep cannot be used with Movsd");
+}
+
+#[test]
+fn system() {
+    do_test("cli\nsti\nwbinvd", "fa fb 0f 09");
+    do_test("rdmsr\nwrmsr", "0f 32 0f 30");
+    do_test("iretq\nsysret\nswapgs", "48 cf 0f 07 0f 01 f8");
+    do_test("sysretq", "48 0f 07");
+    do_test("lgdt [rax]\nlidt [rdi]", "0f 01 10 0f 01 1f");
+    do_test("invlpg [rax]", "0f 01 38");
+    do_test("xsave [rsp]\nxrstor [rax]", "0f ae 24 24 0f ae 28");
+    do_test("ltr ax\nltr word ptr [rax]", "0f 00 d8 0f 00 18");
+    do_test("mov cr3,rax\nmov rax,cr0", "0f 22 d8 0f 20 c0");
+    do_test("mov cr4,rdi\nmov cr8,r9", "0f 22 e7 45 0f 22 c1");
+    do_test("mov dr7,rax\nmov rbx,dr6", "0f 23 f8 0f 21 f3");
+    do_test("in al,96\nin eax,dx\nin ax,dx", "e4 60 ed 66 ed");
+    do_test("out 128,al\nout dx,eax", "e6 80 ef");
+    do_test("int 128", "cd 80");
+    do_test("int 0x80", "cd 80");
+    do_test_error("mov cr0,eax", "unexpected Eax");
+    do_test_error("mov cr0,dr0", "unexpected Dr0");
+    do_test_error("in bl,dx", "unexpected Bl");
+    do_test_error("in al,cx", "unexpected Cx");
+    do_test_error("int 256", "integer out of range: 256");
+    do_test_error("lgdt rax", "unexpected Rax");
+    do_test_error(".code32\nsysretq", "Sysretq is not valid in 32-bit mode");
+}
+
+#[test]
+fn far_branch() {
+    do_test(".code32\nljmp 8:4096", "ea 00 10 00 00 08 00");
+    do_test(".code32\nlcall 16:4096", "9a 00 10 00 00 10 00");
+    do_test(".code16\nljmp 8:4096", "ea 00 10 08 00");
+    do_test("jmp fword ptr [rax]", "ff 28");
+    do_test("jmp far [rax]", "ff 28");
+    do_test("call far ptr [r8]", "41 ff 18");
+    do_test("ljmp [rax]\nlcall [rbx+8]", "ff 28 ff 5b 08");
+    do_test(".code32\nljmp [eax]", "ff 28");
+    do_test("retf\nlret 8", "cb ca 08 00");
+    do_test_error(".code32\nljmp 65536:0", "integer out of range: 65536");
+    do_test_error(".code16\nljmp 0:65536", "integer out of range: 65536");
+    do_test_error("ljmp 8:4096", "invalid operands for Jmp");
+    do_test_error("lcall 16:target", "invalid operands for Call");
+    do_test_error("ljmp rax", "invalid operands for Jmp");
+    do_test_error("lcall qword ptr [rax]", "invalid operands for Call");
+    do_test_error("mov rax,fword ptr [rax]", "unexpected Fword operand");
+
+    let text = text_section_with_relas(".code32\nljmp 8:target+4");
+    assert_eq!(bytes_to_str(&text.data), "ea 00 00 00 00 08 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32));
+    assert_eq!(text.relas[0].offset, 1);
+    assert_eq!(text.relas[0].addend, 4);
+
+    let text = text_section_with_relas(".code32\nljmp 0x08:target");
+    assert_eq!(bytes_to_str(&text.data), "ea 00 00 00 00 08 00");
+    assert_eq!(text.relas[0].offset, 1);
+
+    let text = text_section_with_relas(".code16\nljmp 8:target");
+    assert_eq!(bytes_to_str(&text.data), "ea 00 00 08 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs16));
+    assert_eq!(text.relas[0].offset, 1);
+}
+
+#[test]
+fn segment_register() {
+    do_test("mov ds,ax\nmov ss,eax", "8e d8 8e d0");
+    do_test("mov es,rax", "48 8e c0");
+    do_test("mov ax,ss\nmov eax,fs", "66 8c d0 8c e0");
+    do_test("mov word ptr [rax],gs", "8c 28");
+    do_test("mov fs,word ptr [rdi]", "8e 27");
+    do_test(
+        "push fs\npush gs\npop fs\npop gs",
+        "0f a0 0f a8 0f a1 0f a9",
+    );
+    do_test_error("mov cs,ax", "unexpected Cs");
+    do_test_error("mov ds,al", "unexpected Al");
+    do_test_error("push ds", "unexpected Ds");
+}
+
+#[test]
+fn bit() {
+    do_test("bt rax,rcx", "48 0f a3 c8");
+    do_test("bts dword ptr [rdi],5", "0f ba 2f 05");
+    do_test("btr ax,3", "66 0f ba f0 03");
+    do_test("lock btc qword ptr [rax],rdx", "f0 48 0f bb 10");
+    do_test("bsf eax,ecx\nbsr rax,[rdi]", "0f bc c1 48 0f bd 07");
+    do_test("popcnt rax,rbx", "f3 48 0f b8 c3");
+    do_test("lzcnt ax,word ptr [rsi]", "66 f3 0f bd 06");
+    do_test("tzcnt r8d,r9d", "f3 45 0f bc c1");
+    do_test(".code16\npopcnt ax,bx", "f3 0f b8 c3");
+    do_test_error("bt al,1", "unexpected Al");
+    do_test_error("bt eax,rcx", "unexpected Rcx");
+    do_test_error("bt [rax],1", "operand size is not specified");
+    do_test_error("bt eax,256", "integer out of range: 256");
+    do_test_error("lock bt [rax],ecx", "Lock cannot be used with Bt");
+
+    let text = text_section_with_relas("bts qword ptr [rip+bitmap],5");
+    assert_eq!(bytes_to_str(&text.data), "48 0f ba 2d 00 00 00 00 05");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 4);
+    assert_eq!(text.relas[0].addend, -5);
+}
+
+#[test]
+fn bmi() {
+    do_test(
+        "andn eax,ebx,ecx\nandn rax,rbx,rcx",
+        "c4 e2 60 f2 c1 c4 e2 e0 f2 c1",
+    );
+    do_test("andn eax,ebx,r9d", "c4 c2 60 f2 c1");
+    do_test(
+        "blsr eax,ecx\nblsi rax,[rdi]",
+        "c4 e2 78 f3 c9 c4 e2 f8 f3 1f",
+    );
+    do_test("bzhi eax,ebx,ecx", "c4 e2 70 f5 c3");
+    do_test(
+        "pdep eax,ebx,ecx\npext rax,rbx,rcx",
+        "c4 e2 63 f5 c1 c4 e2 e2 f5 c1",
+    );
+    do_test(
+        "shlx eax,ebx,ecx\nsarx eax,[rdi],ecx",
+        "c4 e2 71 f7 c3 c4 e2 72 f7 07",
+    );
+    do_test("rorx eax,ebx,4", "c4 e3 7b f0 c3 04");
+    do_test(".code32\nandn eax,ebx,ecx", "c4 e2 60 f2 c1");
+    do_test_error("andn ax,bx,cx", "unexpected Ax");
+    do_test_error("andn eax,ebx,rcx", "unexpected Rcx");
+    do_test_error("shlx eax,ebx,5", "invalid operands for Shlx");
+
+    let text = text_section_with_relas("rorx eax,[rip+x],3");
+    assert_eq!(bytes_to_str(&text.data), "c4 e3 7b f0 05 00 00 00 00 03");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 5);
+    assert_eq!(text.relas[0].addend, -5);
+}
+
+#[test]
+fn atomic() {
+    do_test("lock cmpxchg [rdi],rsi", "f0 48 0f b1 37");
+    do_test("cmpxchg ecx,edx", "0f b1 d1");
+    do_test("lock cmpxchg byte ptr [rax],bl", "f0 0f b0 18");
+    do_test("lock xadd [rax],eax", "f0 0f c1 00");
+    do_test("xadd word ptr [rbx],cx", "66 0f c1 0b");
+    do_test("xchg [rax],rbx\nxchg rbx,[rax]", "48 87 18 48 87 18");
+    do_test("lock xchg [rax],sil", "f0 40 86 30");
+    do_test("lock cmpxchg8b [rdi]", "f0 0f c7 0f");
+    do_test("lock cmpxchg16b [rdi]", "f0 48 0f c7 0f");
+    do_test("lock inc dword ptr [rax]", "f0 ff 00");
+    do_test(
+        "label: lock inc dword ptr [rax]\njmp label",
+        "f0 ff 00 eb fb",
+    );
+    do_test(".code16\nlock xadd [eax],ax", "f0 67 0f c1 00");
+    do_test_error("lock mov [rax],rbx", "Lock cannot be used with Mov");
+    do_test_error("lock add rax,[rbx]", "Lock cannot be used with Add");
+    do_test_error("xadd eax,bx", "unexpected Eax");
+    do_test_error("xadd qword ptr [rax],ebx", "unexpected Qword operand");
+    do_test_error("cmpxchg8b dword ptr [rax]", "unexpected Dword operand");
+    do_test_error(
+        ".code32\ncmpxchg16b [eax]",
+        "Cmpxchg16b is not valid in 32-bit mode",
+    );
+
+    let text = text_section_with_relas("lock add qword ptr [rip+counter],1");
+    assert_eq!(bytes_to_str(&text.data), "f0 48 83 05 00 00 00 00 01");
+    assert!(matches!(text.relas[0].typ, RelaType::Pc32));
+    assert_eq!(text.relas[0].offset, 4);
+    assert_eq!(text.relas[0].addend, -5);
+}
+
+#[test]
+fn synchronisation() {
+    do_test("mfence\nlfence\nsfence", "0f ae f0 0f ae e8 0f ae f8");
+    do_test("pause\nrdtsc\nrdtscp\ncpuid", "f3 90 0f 31 0f 01 f9 0f a2");
+    do_test(
+        "prefetcht0 [rax]\nprefetchnta byte ptr [rax+8]\nprefetchw [rcx]",
+        "0f 18 08 0f 18 40 08 0f 0d 09",
+    );
+    do_test(
+        "prefetcht1 [r9]\nprefetcht2 [rsp]",
+        "41 0f 18 11 0f 18 1c 24",
+    );
+    do_test_error("prefetcht0 rax", "unexpected Rax");
+    do_test_error("lock mfence", "Lock cannot be used with Mfence");
+}
+
+#[test]
+fn code32() {
+    do_test(".code32\nmov eax,ebx\nmov ax,bx", "89 d8 66 89 d8");
+    do_test(".code32\nmov eax,[ebx+ecx*4+8]", "8b 44 8b 08");
+    do_test(".code32\npush eax\npop ebx\ncall eax", "50 5b ff d0");
+    do_test(".code32\njmp dword ptr [eax]", "ff 20");
+    do_test(".code32\nmov cr0,eax", "0f 22 c0");
+    do_test(".code32\npush ds\npop es", "1e 07");
+    // 32-bit operations encode the same in 32-bit and 64-bit mode
+    do_test(
+        ".code32\nmov eax,1\nadd eax,1000\n.code64\nmov eax,1\nadd eax,1000",
+        "c7 c0 01 00 00 00 81 c0 e8 03 00 00 c7 c0 01 00 00 00 81 c0 e8 03 00 00",
+    );
+    do_test(
+        ".code32\nmov eax,1\n.code64\nmov rax,1",
+        "c7 c0 01 00 00 00 48 c7 c0 01 00 00 00",
+    );
+    do_test(
+        ".code32\nadd al,1\ncmp eax,1000",
+        "80 c0 01 81 f8 e8 03 00 00",
+    );
+    do_test(".code32\nlabel: jmp label", "eb fe");
+    do_test_error(".code32\nmov rax,1", "unexpected Rax");
+    do_test_error(".code32\nmov eax,r8d", "unexpected R8d");
+    do_test_error(".code32\nmov sil,1", "unexpected Sil");
+    do_test_error(".code32\naddsd xmm8,xmm0", "unexpected Xmm8");
+    do_test_error(".code32\nmov eax,[rip+4]", "unexpected Rip");
+    do_test_error(
+        ".code32\nmov eax,[bx]",
+        "16-bit addresses are not supported",
+    );
+    do_test_error(".code32\ninc qword ptr [eax]", "unexpected Qword operand");
+    do_test_error(".code32\ncdqe", "Cdqe is not valid in 32-bit mode");
+    do_test_error(".code32\npop cs", "unexpected Cs");
+
+    let text = text_section_with_relas(".code32\nmov eax,offset msg\nmov eax,[ebx+msg]");
+    assert_eq!(
+        bytes_to_str(&text.data),
+        "c7 c0 00 00 00 00 8b 83 00 00 00 00"
+    );
+    assert!(matches!(text.relas[0].typ, RelaType::Abs32));
+    assert_eq!(text.relas[0].offset, 2);
+    assert!(matches!(text.relas[1].typ, RelaType::Abs32));
+    assert_eq!(text.relas[1].offset, 8);
+}
+
+#[test]
+fn code16() {
+    do_test(".code16\nmov ax,bx\nmov eax,ebx", "89 d8 66 89 d8");
+    do_test(".code16\nmov ax,[ebx]", "67 8b 03");
+    do_test(".code16\nadd ax,1000", "81 c0 e8 03");
+    do_test(".code16\npush ax\npush eax", "50 66 50");
+    do_test(".code16\npush 1\npush 1000", "6a 01 68 e8 03");
+    do_test(".code16\nmovsw\nmovsd\nrep stosb", "a5 66 a5 f3 aa");
+    do_test(".code16\nin ax,dx\nout dx,eax", "ed 66 ef");
+    do_test(".code16\nmov ds,ax\nmov ax,cs", "8e d8 8c c8");
+    do_test(".code16\ncbw\ncwde", "98 66 98");
+    do_test(".code16\nlgdt [eax]", "67 0f 01 10");
+    do_test(".code16\naddsd xmm0,qword ptr [eax]", "67 f2 0f 58 00");
+    do_test(".code16\nlabel: jmp label", "eb fe");
+    do_test(".code16\ncall target\ntarget: ret", "e8 00 00 c3");
+    do_test(
+        ".code16\njz target\n.zero 128\ntarget: ret",
+        &format!("0f 84 80 00 {} c3", vec!["00"; 128].join(" ")),
+    );
+    do_test_error(
+        ".code16\nmov ax,[bx+si]",
+        "16-bit addresses are not supported",
+    );
+    do_test_error(
+        ".code16\nmov ax,[bp-2]",
+        "16-bit addresses are not supported",
+    );
+    do_test_error("mov ax,[si]", "16-bit addresses are not supported");
+    do_test_error(
+        ".code16\njrcxz label\nlabel: ret",
+        "Jrcxz is not valid in 16-bit mode",
+    );
+
+    let text = text_section_with_relas(".code16\nmov ax,offset msg\ncall ext");
+    assert_eq!(bytes_to_str(&text.data), "c7 c0 00 00 e8 00 00");
+    assert!(matches!(text.relas[0].typ, RelaType::Abs16));
+    assert_eq!(text.relas[0].offset, 2);
+    assert!(matches!(text.relas[1].typ, RelaType::Pc16));
+    assert_eq!(text.relas[1].offset, 5);
+    assert_eq!(text.relas[1].addend, -2);
+}
+
+#[test]
+fn operand_count() {
+    do_test("ret\nlabel: ret", "c3 c3");
+    do_test("hlt ; comment\nret", "f4 c3");
+    do_test("add rax,\n1", "48 83 c0 01");
+    do_test_error("add rax", "Add does not take 1 operands");
+    do_test_error("push\n1", "Push does not take 0 operands");
+    do_test_error("ret rax", "Ret does not take 1 operands");
+    do_test_error("shld rax,rbx", "Shld does not take 2 operands");
+}
+
+#[test]
+fn case_insensitive() {
+    do_test("MOV RAX, 1", "48 c7 c0 01 00 00 00");
+    do_test("Push R8", "41 50");
+    do_test("REP MOVSB", "f3 a4");
+    do_test("mov eax, DWORD PTR [rax]", "8b 00");
+    do_test("vaddpd zmm0{K1}{Z},zmm1,zmm2", "62 f1 f5 c9 58 c2");
+    do_test(".GLOBAL main\njmp main\nmain: ret", "e9 00 00 00 00 c3");
+    // the names of symbols are still case-sensitive
+    do_test("jmp Foo\nfoo: ret\nFoo: ret", "eb 01 c3 c3");
+    do_test_error("foo:\n.tse FOO, 0, 8, 8", "undefined symbol: 'FOO'");
+    do_test_error(".Unknown", "unknown pseudo-op: '.Unknown'");
+    // mnemonics and prefixes still name symbols as written
+    do_test("call Out\nOut: ret", "e8 00 00 00 00 c3");
+    do_test("jmp Std\nStd: ret", "eb 00 c3");
+    do_test("Int: jmp Int", "eb fe");
+    do_test("Lock: jmp Lock", "eb fe");
+    // while registers and keywords are always operands
+    do_test_error("K1: ret", "unexpected Register(K1)");
+    do_test_error("jmp K1\nK1: ret", "unexpected Register(K1)");
+    do_test_error("Ptr: ret", "unexpected Keyword(Ptr)");
+    do_test("LJMP [rax]", "ff 28");
+}
+
 #[test]
 fn zero() {
     do_test(".zero 0", ""); // skip
@@ -317,25 +1379,109 @@ fn ascii() {
     do_test(r#".ascii "\r\n""#, "0d 0a"); // skip
 }
 
+#[test]
+fn section_stack() {
+    do_test(".subsection 1\nret\n.subsection 0\nhlt", "f4 c3");
+    do_test(".pushsection .ex_table\nhlt\n.popsection\nret", "c3");
+    do_test(".pushsection .ex_table, 1\nhlt\n.previous\nret", "c3");
+    do_test(".data\n.zero 1\n.previous\nret\n.previous\n.zero 1", "c3");
+    do_test(
+        ".subsection 1\nlabel: ret\n.subsection 0\njmp label",
+        "eb 00 c3",
+    );
+    do_test_error(".subsection -1", "subsection out of range: -1");
+    do_test_error(".pushsection .ex_table, -2", "subsection out of range: -2");
+    do_test_error(
+        ".subsection 4294967296",
+        "subsection out of range: 4294967296",
+    );
+}
+
+#[test]
+fn tse() {
+    let obj = generate("main:\n.tse 8, 16, 8\nret\n.tse main, -8, 0, 1");
+    let tse_info = obj
+        .sections
+        .into_iter()
+        .find(|section| section.name == SectionName::TseInfo)
+        .unwrap();
+
+    assert_eq!(
+        bytes_to_str(&tse_info.data[..24]),
+        "08 00 00 00 00 00 00 00 10 00 00 00 00 00 00 00 08 00 00 00 00 00 00 00"
+    );
+    assert_eq!(tse_info.data.len(), 64);
+    assert_eq!(tse_info.relas.len(), 2);
+    assert_eq!(tse_info.relas[0].offset, 24);
+    assert_eq!(tse_info.relas[1].offset, 56);
+
+    do_test_error(".tse 0, 8, 8", "no preceding label to attach to");
+    do_test_error("main:\n.tse 0, -1, 8", "size must not be negative: -1");
+    do_test_error(
+        "main:\n.tse 0, 8, 12",
+        "alignment must be a power of two: 12",
+    );
+    do_test_error(".tse foo, 0, 8, 8", "undefined symbol: 'foo'");
+}
+
 fn do_test(source: &str, expected_output: &str) {
+    let actual_output = text_section(generate(source));
+
+    assert_eq!(expected_output, actual_output, "failed with '{}'", source);
+}
+
+fn do_test_error(source: &str, expected_message: &str) {
+    let err = try_generate(source).unwrap_err();
+
+    assert_eq!(
+        expected_message,
+        err.kind.to_string(),
+        "failed with '{}'",
+        source
+    );
+}
+
+fn generate(source: &str) -> Object {
+    try_generate(source).unwrap()
+}
+
+fn generate_with(source: &str, options: Options) -> Object {
+    let source_file = SourceFile {
+        filename: "".to_string(),
+        content: source.to_string(),
+    };
+    lexer::tokenize(source_file)
+        .and_then(|tokens| parser::parse(tokens))
+        .and_then(|insts| gen_code::generate_with(insts, options))
+        .unwrap()
+}
+
+fn try_generate(source: &str) -> Result<Object, Error> {
     let source_file = SourceFile {
         filename: "".to_string(),
         content: source.to_string(),
     };
-    let obj = lexer::tokenize(source_file)
+    lexer::tokenize(source_file)
         .and_then(|tokens| parser::parse(tokens))
         .and_then(|insts| gen_code::generate(insts))
-        .unwrap();
+}
 
+fn text_section(obj: Object) -> String {
     let text_section = obj
         .sections
         .into_iter()
         .find(|section| section.name == SectionName::Text)
         .unwrap();
 
-    let actual_output = bytes_to_str(&text_section.data);
+    bytes_to_str(&text_section.data)
+}
 
-    assert_eq!(expected_output, actual_output, "failed with '{}'", source);
+fn text_section_with_relas(source: &str) -> Section {
+    generate(source)
+        .sections
+        .into_iter()
+        .find(|section| section.name == SectionName::Text)
+        .unwrap()
 }
 
 fn bytes_to_str(bytes: &[u8]) -> String {